@@ -0,0 +1,66 @@
+//! Compare memory and accuracy of a HyperLogLog with an exact `HashSet` across cardinalities
+//!
+//! Run with `cargo run --release --example scaling`, add `--full` to go up to 10^8 elements.
+
+use std::collections::HashSet;
+use std::mem;
+
+use clap::Parser;
+
+use hll_rs::HyperLogLog;
+
+#[derive(Parser)]
+struct Args {
+    /// Number of index bits to use in HLL
+    #[arg(long, default_value_t = 14)]
+    index_bits: u8,
+    /// Sweep up to 10^8 elements instead of 10^6
+    #[arg(long)]
+    full: bool,
+}
+
+/// Approximate bytes used by a `HashSet<u64>`, one value and one control byte per bucket
+///
+/// `capacity` is the number of elements fitting before a resize. The table keeps a power of two
+/// of buckets at most 7/8 full, so the bucket count is recovered from it. The control bytes are
+/// padded by one SIMD group of 16 bytes.
+fn hash_set_bytes(set: &HashSet<u64>) -> usize {
+    let capacity = set.capacity();
+    let buckets = match capacity {
+        0 => return mem::size_of::<HashSet<u64>>(),
+        // Small tables may use every bucket but one
+        1..=7 => (capacity + 1).next_power_of_two(),
+        _ => (capacity * 8 / 7).next_power_of_two(),
+    };
+    mem::size_of::<HashSet<u64>>() + buckets * (mem::size_of::<u64>() + 1) + 16
+}
+
+fn main() -> Result<(), String> {
+    let args = Args::parse();
+    let max_exp: u32 = if args.full { 8 } else { 6 };
+
+    println!(
+        "{:>12} {:>12} {:>10} {:>14} {:>8}",
+        "cardinality", "hll bytes", "hll error", "hashset bytes", "ratio"
+    );
+    for exp in 3..=max_exp {
+        let cardinality: u64 = 10_u64.pow(exp);
+        let mut hll = HyperLogLog::new(args.index_bits)?;
+        let mut set: HashSet<u64> = HashSet::new();
+        for value in 0..cardinality {
+            hll.add_u64(value);
+            set.insert(value);
+        }
+        let error = (hll.count() - set.len() as f64).abs() / set.len() as f64;
+        let exact_bytes = hash_set_bytes(&set);
+        println!(
+            "{:>12} {:>12} {:>9.2}% {:>14} {:>7.0}x",
+            cardinality,
+            hll.memory_bytes(),
+            error * 100.0,
+            exact_bytes,
+            exact_bytes as f64 / hll.memory_bytes() as f64
+        );
+    }
+    Ok(())
+}
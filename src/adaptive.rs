@@ -0,0 +1,99 @@
+//! Counter switching from an exact set to a HyperLogLog past a threshold
+
+use std::collections::HashSet;
+use std::hash::Hash;
+
+use crate::{helpers, HyperLogLog};
+
+#[derive(Debug)]
+enum State<T> {
+    Exact(HashSet<T>),
+    Approximate(HyperLogLog),
+}
+
+/// Distinct counter that is exact for small cardinalities
+///
+/// Elements are stored in a `HashSet` until there are more than `threshold` of them, after which
+/// they are migrated to a `HyperLogLog` and the set is dropped.
+#[derive(Debug)]
+pub struct AdaptiveCounter<T> {
+    state: State<T>,
+    index_bits: u8,
+    threshold: usize,
+}
+
+impl<T: Hash + Eq> AdaptiveCounter<T> {
+    /// Create a new counter migrating to a HLL with `index_bits` after `threshold` elements
+    pub fn new(index_bits: u8, threshold: usize) -> Result<Self, String> {
+        // Validate index bits before any elements are added
+        helpers::validate_index_bits(index_bits)?;
+        Ok(Self { state: State::Exact(HashSet::new()), index_bits, threshold })
+    }
+
+    /// Add a new element to the counter
+    pub fn add(&mut self, value: T) {
+        match &mut self.state {
+            State::Exact(set) => {
+                set.insert(value);
+                if set.len() > self.threshold {
+                    self.migrate();
+                }
+            }
+            State::Approximate(hll) => hll.add(&value),
+        }
+    }
+
+    /// Move the stored elements into a HLL
+    fn migrate(&mut self) {
+        let mut hll = HyperLogLog::new(self.index_bits).unwrap();
+        if let State::Exact(set) = &self.state {
+            for value in set.iter() {
+                hll.add(value);
+            }
+        }
+        self.state = State::Approximate(hll);
+    }
+
+    /// Check if the counter is still counting exactly
+    pub fn is_exact(&self) -> bool {
+        matches!(self.state, State::Exact(_))
+    }
+
+    /// Count the cardinality of the added elements
+    pub fn count(&self) -> f64 {
+        match &self.state {
+            State::Exact(set) => set.len() as f64,
+            State::Approximate(hll) => hll.count(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adaptive_invalid_bits() {
+        assert!(AdaptiveCounter::<u32>::new(2, 10).is_err());
+    }
+
+    #[test]
+    fn test_adaptive_migration() {
+        let mut counter = AdaptiveCounter::new(10, 200).unwrap();
+        for i in 0..200_u32 {
+            counter.add(i);
+            counter.add(i);
+        }
+        assert!(counter.is_exact());
+        assert_eq!(counter.count(), 200.0);
+        counter.add(200);
+        assert!(!counter.is_exact());
+        let error = (counter.count() - 201.0).abs() / 201.0;
+        assert!(error < 0.05, "{}", error);
+        for i in 201..1000_u32 {
+            counter.add(i);
+        }
+        let error = (counter.count() - 1000.0).abs() / 1000.0;
+        assert!(error < 0.1, "{}", error);
+    }
+}
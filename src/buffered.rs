@@ -0,0 +1,85 @@
+//! Buffered adder updating HyperLogLog registers in index order
+
+use std::hash::Hash;
+
+use crate::{helpers, HyperLogLog};
+
+/// Adder collecting hashes and applying them to a HLL sorted by register index
+///
+/// Hashes are buffered until `capacity` of them are collected, then sorted so the register writes
+/// sweep the registers once instead of jumping around. The register index is the top bits of the
+/// hash, so sorting the hashes sorts the indexes. Remaining hashes are flushed on `Drop`.
+#[derive(Debug)]
+pub struct BufferedAdder<'a> {
+    hll: &'a mut HyperLogLog,
+    buffer: Vec<u32>,
+    capacity: usize,
+}
+
+impl<'a> BufferedAdder<'a> {
+    /// Create a new adder buffering up to `capacity` hashes for `hll`
+    pub fn new(hll: &'a mut HyperLogLog, capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self { hll, buffer: Vec::with_capacity(capacity), capacity }
+    }
+
+    /// Add a new hashable element, hashed the same way as `HyperLogLog::add`
+    pub fn add<T: Hash>(&mut self, value: &T) {
+        let hash = helpers::hash_value_32_seeded(value, self.hll.seed(), self.hll.hash_algo());
+        self.add_hash(hash);
+    }
+
+    /// Add an already hashed element, see `HyperLogLog::add_hash`
+    pub fn add_hash(&mut self, hash: u32) {
+        self.buffer.push(hash);
+        if self.buffer.len() >= self.capacity {
+            self.flush();
+        }
+    }
+
+    /// Apply all buffered hashes to the set
+    pub fn flush(&mut self) {
+        self.buffer.sort_unstable();
+        for hash in self.buffer.drain(..) {
+            self.hll.add_hash(hash);
+        }
+    }
+}
+
+impl Drop for BufferedAdder<'_> {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buffered_matches_add() {
+        let mut expected = HyperLogLog::with_seed(12, 3).unwrap();
+        let mut hll = HyperLogLog::with_seed(12, 3).unwrap();
+        {
+            let mut adder = BufferedAdder::new(&mut hll, 256);
+            for i in 0..10_000_u32 {
+                adder.add(&i);
+                expected.add(&i);
+            }
+        }
+        assert_eq!(hll.registers_as_slice(), expected.registers_as_slice());
+        assert_eq!(hll.count(), expected.count());
+    }
+
+    #[test]
+    fn test_buffered_flush() {
+        let mut hll = HyperLogLog::new(8).unwrap();
+        let mut adder = BufferedAdder::new(&mut hll, 100);
+        adder.add(&"moros");
+        adder.flush();
+        drop(adder);
+        let mut expected = HyperLogLog::new(8).unwrap();
+        expected.add(&"moros");
+        assert_eq!(hll.registers_as_slice(), expected.registers_as_slice());
+    }
+}
@@ -0,0 +1,99 @@
+//! Fixed byte encoding of values for hashing independently of `Hash`
+
+/// Value with a byte encoding that is the same on every platform and compiler version
+///
+/// Unlike `Hash`, whose output std does not promise to keep stable, the layout is fixed by this
+/// crate: integers are their little endian bytes with `usize`/`isize` widened to 64 bits, and
+/// strings and byte slices are their length as a little endian `u64` followed by their bytes.
+/// The length prefix keeps concatenated encodings unambiguous, e.g. `("ab", "c")` and
+/// `("a", "bc")` encode differently.
+pub trait CanonicalEncode {
+    /// Append the encoding of `self` to `out`
+    fn encode(&self, out: &mut Vec<u8>);
+}
+
+macro_rules! impl_canonical_int {
+    ($($int:ty),*) => {
+        $(
+            impl CanonicalEncode for $int {
+                fn encode(&self, out: &mut Vec<u8>) {
+                    out.extend_from_slice(&self.to_le_bytes());
+                }
+            }
+        )*
+    };
+}
+
+impl_canonical_int!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+impl CanonicalEncode for usize {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as u64).encode(out);
+    }
+}
+
+impl CanonicalEncode for isize {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as i64).encode(out);
+    }
+}
+
+impl CanonicalEncode for [u8] {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.len().encode(out);
+        out.extend_from_slice(self);
+    }
+}
+
+impl CanonicalEncode for str {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.as_bytes().encode(out);
+    }
+}
+
+impl CanonicalEncode for String {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.as_str().encode(out);
+    }
+}
+
+impl CanonicalEncode for Vec<u8> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.as_slice().encode(out);
+    }
+}
+
+impl<T: CanonicalEncode + ?Sized> CanonicalEncode for &T {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (**self).encode(out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encoded<T: CanonicalEncode + ?Sized>(value: &T) -> Vec<u8> {
+        let mut out = Vec::new();
+        value.encode(&mut out);
+        out
+    }
+
+    #[test]
+    fn test_canonical_layout() {
+        assert_eq!(encoded(&1_u32), [1, 0, 0, 0]);
+        assert_eq!(encoded(&-2_i16), [0xfe, 0xff]);
+        assert_eq!(encoded(&7_usize), [7, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(encoded(&-1_isize), [0xff; 8]);
+        assert_eq!(encoded("x"), [1, 0, 0, 0, 0, 0, 0, 0, b'x']);
+        assert_eq!(encoded(&"x".to_string()), encoded("x"));
+        assert_eq!(encoded(&[1_u8, 2][..]), [2, 0, 0, 0, 0, 0, 0, 0, 1, 2]);
+        assert_eq!(encoded(&vec![1_u8, 2]), encoded(&[1_u8, 2][..]));
+
+        let mut ab_c = encoded("ab");
+        "c".encode(&mut ab_c);
+        let mut a_bc = encoded("a");
+        "bc".encode(&mut a_bc);
+        assert_ne!(ab_c, a_bc);
+    }
+}
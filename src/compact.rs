@@ -0,0 +1,150 @@
+//! HyperLogLog with 4-bit registers for low cardinalities
+
+use std::hash::Hash;
+
+use crate::{helpers, HashAlgo, HyperLogLog, RankMode};
+
+/// HLL set storing two registers per byte
+///
+/// Registers hold ranks up to `MAX_RANK`, higher ranks saturate to it. With 32-bit hashes a rank
+/// above 15 is only expected once the cardinality reaches tens of thousands per register, so this
+/// halves the memory of the byte-per-register layout for sets that stay small. Elements hash the
+/// same way as in `HyperLogLog::new`.
+#[derive(Debug, Clone)]
+pub struct CompactHyperLogLog {
+    nibbles: Vec<u8>,
+    index_bits: u8,
+}
+
+impl CompactHyperLogLog {
+    /// Highest rank a register can hold
+    pub const MAX_RANK: u8 = 15;
+
+    /// Create a new compact HLL set with `index_bits`, see `HyperLogLog::new`
+    pub fn new(index_bits: u8) -> Result<Self, String> {
+        helpers::validate_index_bits(index_bits)?;
+        let m: usize = helpers::registers_from_bits(&index_bits);
+        Ok(Self { nibbles: vec![0; m / 2], index_bits })
+    }
+
+    /// Number of registers
+    pub fn num_registers(&self) -> usize {
+        self.nibbles.len() * 2
+    }
+
+    /// Value of the register at `index`
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn get(&self, index: usize) -> u8 {
+        let byte = self.nibbles[index / 2];
+        if index.is_multiple_of(2) {
+            byte & 0x0f
+        } else {
+            byte >> 4
+        }
+    }
+
+    /// Overwrite the register at `index` with `value`, saturating at `MAX_RANK`
+    pub fn set(&mut self, index: usize, value: u8) -> Result<(), String> {
+        let len = self.num_registers();
+        let byte = match self.nibbles.get_mut(index / 2) {
+            Some(byte) => byte,
+            None => return Err(format!("Register index {} out of bounds (len {})", index, len)),
+        };
+        let value = value.min(Self::MAX_RANK);
+        if index.is_multiple_of(2) {
+            *byte = (*byte & 0xf0) | value;
+        } else {
+            *byte = (*byte & 0x0f) | value << 4;
+        }
+        Ok(())
+    }
+
+    /// Add a new hashable element to the set
+    pub fn add<T: Hash>(&mut self, value: &T) {
+        let hash = helpers::hash_value_32_seeded(value, 0, HashAlgo::Murmur3);
+        self.add_hash(hash);
+    }
+
+    /// Add an already hashed element to the set
+    pub fn add_hash(&mut self, hash: u32) {
+        let (index, rank) = helpers::index_and_rank(hash, self.index_bits, RankMode::default());
+        let rank = rank.min(Self::MAX_RANK);
+        if rank > self.get(index) {
+            // In bounds as the index has `index_bits` bits
+            self.set(index, rank).unwrap();
+        }
+    }
+
+    /// Iterate over all register values
+    pub fn registers(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..self.num_registers()).map(|x| self.get(x))
+    }
+
+    /// Count the cardinality applying the small and large range corrections
+    pub fn count(&self) -> f64 {
+        let m: f64 = self.num_registers() as f64;
+        let sum: f64 = self.registers().map(helpers::reciprocal_power).sum();
+        let zeros: usize = self.registers().filter(|x| *x == 0).count();
+        let raw = helpers::alpha(m) * m * m / sum;
+        helpers::correct(raw, m, zeros)
+    }
+
+    /// Expand into a byte-per-register set with the same registers
+    pub fn to_hyperloglog(&self) -> HyperLogLog {
+        let mut hll = HyperLogLog::new(self.index_bits).unwrap();
+        for (index, value) in self.registers().enumerate() {
+            hll.set_register(index, value).unwrap();
+        }
+        hll
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compact_get_set() {
+        let mut hll = CompactHyperLogLog::new(4).unwrap();
+        assert_eq!(hll.num_registers(), 16);
+        for index in 0..16 {
+            hll.set(index, index as u8).unwrap();
+        }
+        for index in 0..16 {
+            assert_eq!(hll.get(index), index as u8);
+        }
+        // Neighbours in the same byte are untouched
+        hll.set(4, 0).unwrap();
+        assert_eq!((hll.get(3), hll.get(4), hll.get(5)), (3, 0, 5));
+        hll.set(7, 20).unwrap();
+        assert_eq!((hll.get(6), hll.get(7), hll.get(8)), (6, 15, 8));
+        assert!(hll.set(16, 1).is_err());
+    }
+
+    #[test]
+    fn test_compact_saturates() {
+        let mut hll = CompactHyperLogLog::new(4).unwrap();
+        // index 3, non-index bits all zero gives the maximum rank of 33 - 4
+        let (index, rank) = helpers::index_and_rank(3 << 28, 4, RankMode::default());
+        assert_eq!((index, rank), (3, 29));
+        assert_eq!(rank, helpers::max_rank(4));
+        hll.add_hash(3 << 28);
+        assert_eq!(hll.get(3), CompactHyperLogLog::MAX_RANK);
+        assert_eq!(hll.get(2), 0);
+        hll.add_hash((3 << 28) | 1);
+        assert_eq!(hll.get(3), CompactHyperLogLog::MAX_RANK);
+    }
+
+    #[test]
+    fn test_compact_matches_hyperloglog() {
+        let mut compact = CompactHyperLogLog::new(10).unwrap();
+        let mut hll = HyperLogLog::new(10).unwrap();
+        for i in 0..5_000_u32 {
+            compact.add(&i);
+            hll.add(&i);
+        }
+        assert_eq!(compact.count(), hll.count());
+        assert_eq!(compact.to_hyperloglog().registers_as_slice(), hll.registers_as_slice());
+    }
+}
@@ -0,0 +1,123 @@
+//! HyperLogLog whose registers decay with the time since their last update
+
+use std::hash::Hash;
+
+use crate::{helpers, HashAlgo, HyperLogLog, RankMode};
+
+/// HLL set forgetting elements exponentially with their age
+///
+/// Every register remembers when it was last raised. When counting, a register that was last
+/// updated `age` time units ago keeps `0.5^(age / half_life)` of its value, rounded like
+/// `HyperLogLog::decay`. Elements hash the same way as in `HyperLogLog::new`. Like `decay` this
+/// is experimental, decayed registers do not correspond to any real set of elements.
+#[derive(Debug, Clone)]
+pub struct DecayingHll {
+    register: Vec<u8>,
+    updated: Vec<u64>,
+    index_bits: u8,
+    half_life: u64,
+    now: u64,
+}
+
+impl DecayingHll {
+    /// Create a new decaying set with `index_bits` halving register values every `half_life`
+    pub fn new(index_bits: u8, half_life: u64) -> Result<Self, String> {
+        helpers::validate_index_bits(index_bits)?;
+        if half_life == 0 {
+            return Err("Half life must be positive".to_string());
+        }
+        let m: usize = helpers::registers_from_bits(&index_bits);
+        Ok(Self { register: vec![0; m], updated: vec![0; m], index_bits, half_life, now: 0 })
+    }
+
+    /// Add a new hashable element seen at `timestamp`
+    ///
+    /// The register is replaced when the element's rank is at least the register value decayed
+    /// to `timestamp`, refreshing its update time. Elements arriving out of order, older than the
+    /// last update of their register, are ignored so the register never moves back in time.
+    pub fn add_at<T: Hash>(&mut self, value: &T, timestamp: u64) {
+        let hash = helpers::hash_value_32_seeded(value, 0, HashAlgo::default());
+        let (index, rank) = helpers::index_and_rank(hash, self.index_bits, RankMode::default());
+        if timestamp >= self.updated[index] && rank >= self.decayed(index, timestamp) {
+            self.register[index] = rank;
+            self.updated[index] = timestamp;
+        }
+        self.now = self.now.max(timestamp);
+    }
+
+    /// Latest timestamp an element was added at
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    /// Count the cardinality with the registers decayed to the latest timestamp
+    pub fn count(&self) -> f64 {
+        self.count_at(self.now)
+    }
+
+    /// Count the cardinality with the registers decayed to `now`
+    pub fn count_at(&self, now: u64) -> f64 {
+        let mut hll = HyperLogLog::new(self.index_bits).unwrap();
+        for index in 0..self.register.len() {
+            hll.set_register(index, self.decayed(index, now)).unwrap();
+        }
+        hll.count()
+    }
+
+    /// Value of the register at `index` decayed to `now`
+    fn decayed(&self, index: usize, now: u64) -> u8 {
+        let value = self.register[index];
+        let age = now.saturating_sub(self.updated[index]);
+        let factor = 0.5_f64.powf(age as f64 / self.half_life as f64);
+        let reduction = (value as f64 * (1.0 - factor)).round() as u8;
+        value - reduction
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decaying_invalid() {
+        assert!(DecayingHll::new(3, 10).is_err());
+        assert!(DecayingHll::new(8, 0).is_err());
+    }
+
+    #[test]
+    fn test_decaying_forgets_old_elements() {
+        let mut hll = DecayingHll::new(10, 100).unwrap();
+        let mut expected = HyperLogLog::new(10).unwrap();
+        for i in 0..5000_u32 {
+            hll.add_at(&i, 0);
+            expected.add(&i);
+        }
+        assert_eq!(hll.count(), expected.count());
+        assert!(hll.count_at(100) < hll.count_at(0));
+        assert!(hll.count_at(1000) < hll.count_at(100));
+
+        for i in 5000..5100_u32 {
+            hll.add_at(&i, 2000);
+        }
+        assert_eq!(hll.now(), 2000);
+        // Old elements are gone, only the fresh ones are counted
+        let error = (hll.count() - 100.0).abs() / 100.0;
+        assert!(error < 0.2, "{}", hll.count());
+    }
+
+    #[test]
+    fn test_decaying_out_of_order() {
+        let mut hll = DecayingHll::new(8, 100).unwrap();
+        for i in 0..2000_u32 {
+            hll.add_at(&i, 1000);
+        }
+        let (count, updated) = (hll.count(), hll.updated.clone());
+        // Late arrivals of the same and new elements do not age the fresh registers
+        for i in 0..4000_u32 {
+            hll.add_at(&i, 10);
+        }
+        assert_eq!(hll.updated, updated);
+        assert_eq!(hll.count(), count);
+        assert_eq!(hll.now(), 1000);
+    }
+}
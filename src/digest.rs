@@ -0,0 +1,118 @@
+//! Streaming quantile summary of estimates
+
+/// Bounded summary of a stream of values answering approximate quantile queries
+///
+/// Values are kept as weighted centroids sorted by mean. Once there are more than `capacity`
+/// centroids the two closest ones are merged, so the summary is exact until `capacity` values are
+/// inserted and approximate afterwards.
+#[derive(Debug, Clone)]
+pub struct QuantileDigest {
+    centroids: Vec<(f64, f64)>,
+    capacity: usize,
+}
+
+impl QuantileDigest {
+    /// Create an empty summary keeping up to `capacity` centroids
+    pub fn new(capacity: usize) -> Self {
+        Self { centroids: Vec::new(), capacity: capacity.max(2) }
+    }
+
+    /// Add `value` to the summary, non-finite values are ignored
+    pub fn insert(&mut self, value: f64) {
+        if !value.is_finite() {
+            return;
+        }
+        let position = self.centroids.partition_point(|(mean, _)| *mean < value);
+        self.centroids.insert(position, (value, 1.0));
+        if self.centroids.len() > self.capacity {
+            let closest = (0..self.centroids.len() - 1)
+                .min_by(|a, b| {
+                    let gap = |i: &usize| self.centroids[i + 1].0 - self.centroids[*i].0;
+                    gap(a).total_cmp(&gap(b))
+                })
+                .unwrap();
+            let (mean_b, weight_b) = self.centroids.remove(closest + 1);
+            let (mean_a, weight_a) = self.centroids[closest];
+            let weight = weight_a + weight_b;
+            self.centroids[closest] = ((mean_a * weight_a + mean_b * weight_b) / weight, weight);
+        }
+    }
+
+    /// Number of values inserted
+    pub fn len(&self) -> usize {
+        self.centroids.iter().map(|(_, weight)| *weight).sum::<f64>() as usize
+    }
+
+    /// Whether no value was inserted
+    pub fn is_empty(&self) -> bool {
+        self.centroids.is_empty()
+    }
+
+    /// Bytes allocated for the centroids
+    pub(crate) fn heap_bytes(&self) -> usize {
+        self.centroids.capacity() * std::mem::size_of::<(f64, f64)>()
+    }
+
+    /// Return the approximate `p`-th quantile, `p` is clamped to 0.0..=1.0
+    ///
+    /// Interpolates linearly between centroids, the median of an even number of values is the
+    /// mean of the two middle ones. Returns NaN if the summary is empty.
+    pub fn quantile(&self, p: f64) -> f64 {
+        let (first, last) = match (self.centroids.first(), self.centroids.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return f64::NAN,
+        };
+        let total: f64 = self.centroids.iter().map(|(_, weight)| *weight).sum();
+        let target = p.clamp(0.0, 1.0) * total;
+        // Each centroid sits at the middle of the weight it covers
+        let mut previous = (first.0, first.1 / 2.0);
+        if target <= previous.1 {
+            return first.0;
+        }
+        let mut covered = first.1;
+        for (mean, weight) in &self.centroids[1..] {
+            let center = covered + weight / 2.0;
+            if target <= center {
+                let fraction = (target - previous.1) / (center - previous.1);
+                return previous.0 + fraction * (mean - previous.0);
+            }
+            previous = (*mean, center);
+            covered += weight;
+        }
+        last.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_digest_exact() {
+        let mut digest = QuantileDigest::new(100);
+        assert!(digest.quantile(0.5).is_nan());
+        for value in [5.0, 1.0, 4.0, 2.0, 3.0] {
+            digest.insert(value);
+        }
+        assert_eq!(digest.len(), 5);
+        assert_eq!(digest.quantile(0.5), 3.0);
+        assert_eq!(digest.quantile(0.0), 1.0);
+        assert_eq!(digest.quantile(1.0), 5.0);
+        digest.insert(6.0);
+        assert_eq!(digest.quantile(0.5), 3.5);
+    }
+
+    #[test]
+    fn test_digest_bounded() {
+        let mut digest = QuantileDigest::new(50);
+        for i in 0..10_000 {
+            digest.insert(((i * 7919) % 10_000) as f64);
+        }
+        assert_eq!(digest.len(), 10_000);
+        assert!(digest.centroids.len() <= 50);
+        for p in [0.05, 0.5, 0.95] {
+            let error = (digest.quantile(p) - p * 10_000.0).abs();
+            assert!(error < 500.0, "p {} error {}", p, error);
+        }
+    }
+}
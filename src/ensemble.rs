@@ -0,0 +1,83 @@
+//! Ensemble of independently seeded HyperLogLog sets
+
+use std::hash::Hash;
+
+use crate::HyperLogLog;
+
+/// Ensemble of `k` HLL sets with different seeds
+///
+/// Every element is added to all sets and the cardinality is the median of their estimates,
+/// which reduces the variance caused by an unlucky hash function.
+#[derive(Debug)]
+pub struct EnsembleHyperLogLog {
+    sketches: Vec<HyperLogLog>,
+}
+
+impl EnsembleHyperLogLog {
+    /// Create `k` sets with `index_bits` seeded with `base_seed`, `base_seed + 1`, ...
+    pub fn new(index_bits: u8, k: usize, base_seed: u32) -> Result<Self, String> {
+        if k == 0 {
+            return Err("Ensemble must contain at least one set".to_string());
+        }
+        let sketches = (0..k as u32)
+            .map(|i| HyperLogLog::with_seed(index_bits, base_seed.wrapping_add(i)))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { sketches })
+    }
+
+    /// Add a new hashable element to all sets
+    pub fn add<T: Hash>(&mut self, value: &T) {
+        for sketch in self.sketches.iter_mut() {
+            sketch.add(value);
+        }
+    }
+
+    /// Sets of the ensemble
+    pub fn sketches(&self) -> &[HyperLogLog] {
+        &self.sketches
+    }
+
+    /// Count the cardinality as the median of the set estimates
+    pub fn count(&self) -> f64 {
+        let mut estimates: Vec<f64> = self.sketches.iter().map(|x| x.count()).collect();
+        estimates.sort_by(f64::total_cmp);
+        let mid = estimates.len() / 2;
+        if estimates.len().is_multiple_of(2) {
+            (estimates[mid - 1] + estimates[mid]) / 2.0
+        } else {
+            estimates[mid]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ensemble_invalid() {
+        assert!(EnsembleHyperLogLog::new(8, 0, 0).is_err());
+        assert!(EnsembleHyperLogLog::new(2, 3, 0).is_err());
+    }
+
+    #[test]
+    fn test_ensemble_error() {
+        let mut ensemble_error = 0.0;
+        let mut single_error = 0.0;
+        let trials = 10;
+        for trial in 0..trials {
+            let mut ensemble = EnsembleHyperLogLog::new(6, 5, trial * 5 + 1).unwrap();
+            let n = 5000;
+            for i in 0..n {
+                ensemble.add(&i);
+            }
+            ensemble_error += (ensemble.count() - n as f64).abs() / n as f64;
+            single_error += ensemble
+                .sketches()
+                .iter()
+                .map(|x| (x.count() - n as f64).abs() / n as f64)
+                .sum::<f64>() / ensemble.sketches().len() as f64;
+        }
+        assert!(ensemble_error <= single_error, "{} > {}", ensemble_error, single_error);
+    }
+}
@@ -0,0 +1,3269 @@
+//! Implementation of HyperLogLog
+//!
+//! [1]: https://algo.inria.fr/flajolet/Publications/FlFuGaMe07.pdf
+//! [2]: https://en.wikipedia.org/wiki/HyperLogLog
+//! [3]: https://arxiv.org/abs/1612.02284
+
+use std::cmp;
+use std::collections::VecDeque;
+use std::fmt;
+use std::hash::Hash;
+use std::io::BufRead;
+use std::ops::Index;
+
+mod adaptive;
+mod buffered;
+mod canonical;
+mod compact;
+mod decaying;
+mod digest;
+mod ensemble;
+mod pool;
+mod readonly;
+mod sharded;
+mod tracked;
+mod writer;
+
+pub use adaptive::AdaptiveCounter;
+pub use buffered::BufferedAdder;
+pub use canonical::CanonicalEncode;
+pub use compact::CompactHyperLogLog;
+pub use decaying::DecayingHll;
+pub use digest::QuantileDigest;
+pub use ensemble::EnsembleHyperLogLog;
+pub use pool::HllPool;
+pub use readonly::ReadOnlyHll;
+pub use sharded::ShardedHll;
+pub use tracked::TrackedHll;
+pub use writer::HllWriter;
+
+/// Version of the byte format written by `HyperLogLog::to_bytes`
+pub const FORMAT_VERSION: u8 = 2;
+
+/// Size of the 32 bit hash space
+const HASH_SPACE: f64 = 4_294_967_296.0;
+
+/// Estimator used to turn the registers into a cardinality estimate
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum EstimationMethod {
+    /// Raw harmonic mean estimate without any corrections
+    Raw,
+    /// Raw estimate with the small range (linear counting) and large range corrections of [1]
+    #[default]
+    Corrected,
+    /// LogLog-Beta estimate of [3], only available with 14 index bits
+    Beta,
+    /// Geometric mean estimate of the original LogLog algorithm
+    LogLog,
+}
+
+/// How the rank of a hash is computed from the bits not used as the register index
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RankMode {
+    /// Position of the lowest set bit, counted from the least significant bit
+    #[default]
+    TrailingZeros,
+    /// Position of the highest set bit, counted from the bit right after the index bits. This is
+    /// the rank used in [1] and most other implementations.
+    LeadingZeros,
+}
+
+/// Range correction applied by `HyperLogLog::count`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Regime {
+    /// Small range, estimated from the number of empty registers
+    LinearCounting,
+    /// Raw estimate without a correction
+    Raw,
+    /// Large range, corrected for hash collisions in the 32 bit hash space
+    LargeRange,
+}
+
+/// Estimated number of distinct elements, as returned by `HyperLogLog::cardinality`
+///
+/// Formats like the wrapped `f64`, so `{:.0}` prints a whole number of elements. Converting to
+/// `u64` rounds to the nearest integer, saturating at the bounds; NaN converts to 0.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Cardinality(pub f64);
+
+impl fmt::Display for Cardinality {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl From<Cardinality> for f64 {
+    fn from(cardinality: Cardinality) -> f64 {
+        cardinality.0
+    }
+}
+
+impl From<Cardinality> for u64 {
+    fn from(cardinality: Cardinality) -> u64 {
+        cardinality.0.round() as u64
+    }
+}
+
+/// Hash function used for hashing the elements
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgo {
+    /// 32 bit MurmurHash3
+    #[default]
+    Murmur3,
+    /// 32 bit Fowler-Noll-Vo (FNV-1a)
+    Fnv,
+}
+
+#[derive(Debug, Clone)]
+pub struct HyperLogLog {
+    register: Vec<u8>,
+    index_bits: u8,
+    seed: u32,
+    rank_mode: RankMode,
+    hash_algo: HashAlgo,
+    history: VecDeque<f64>,
+    history_capacity: usize,
+    alpha_override: Option<f64>,
+    /// Running sum of `2^-register` over all registers
+    indicator_sum: f64,
+    /// Running number of empty registers
+    zeros: usize,
+    /// Bumped whenever the estimate may have changed
+    generation: u64,
+    /// `alpha * m^2`, kept in sync with the number of registers and the alpha override
+    scale: f64
+}
+
+impl Default for HyperLogLog {
+    /// Creates a HyperLogLog with 4 bits as `index_bits`
+    fn default() -> Self {
+        Self::new(4).unwrap()
+    }
+}
+
+/// Create a `HyperLogLog` with a number of index bits
+#[macro_export]
+macro_rules! HLL {
+    ($index_bits:expr) => {
+        $crate::HyperLogLog::new($index_bits).unwrap()
+    };
+}
+
+impl HyperLogLog {
+    /// Create a new HyperLogLog(HLL) set with first `index_bits` used as register indexes
+    ///
+    /// ```
+    /// use hll_rs::HyperLogLog;
+    ///
+    /// let hll = HyperLogLog::new(10).unwrap();
+    /// assert_eq!(hll.num_registers(), 1024);
+    /// assert!(HyperLogLog::new(17).is_err());
+    /// ```
+    pub fn new(index_bits: u8) -> Result<Self, String> {
+        helpers::validate_index_bits(index_bits)?;
+        Ok(Self::new_unchecked(index_bits))
+    }
+
+    /// Create a new HLL set like `new` without validating `index_bits` in release builds
+    ///
+    /// `index_bits` must be within 4..=16, which is only asserted in debug builds. Other values
+    /// do not cause undefined behavior, but later methods panic or give meaningless estimates.
+    pub fn new_unchecked(index_bits: u8) -> Self {
+        debug_assert!(
+            (4..=16).contains(&index_bits),
+            "Number of index bits must be between 4 and 16 (was {})",
+            index_bits
+        );
+        let m: usize = helpers::registers_from_bits(&index_bits);
+        Self::from_zeroed_buffer(index_bits, vec![0; m])
+    }
+
+    /// Create a new HLL set around `register`, which must hold `2^index_bits` zeros
+    fn from_zeroed_buffer(index_bits: u8, register: Vec<u8>) -> Self {
+        let m: usize = register.len();
+        Self {
+            register,
+            index_bits,
+            seed: 0,
+            rank_mode: RankMode::default(),
+            hash_algo: HashAlgo::default(),
+            history: VecDeque::new(),
+            history_capacity: 0,
+            alpha_override: None,
+            indicator_sum: m as f64,
+            zeros: m,
+            generation: 0,
+            scale: helpers::alpha(m as f64) * m.pow(2) as f64
+        }
+    }
+
+    /// Create a new HLL set computing ranks with `rank_mode`
+    ///
+    /// Both modes give the same distribution of ranks, but sets with different modes cannot be
+    /// merged.
+    pub fn with_rank_mode(index_bits: u8, rank_mode: RankMode) -> Result<Self, String> {
+        let mut hll = Self::new(index_bits)?;
+        hll.rank_mode = rank_mode;
+        Ok(hll)
+    }
+
+    /// Create a new HLL set with `index_bits` from already hashed elements, see `add_hash`
+    pub fn from_hashes(hashes: &[u32], index_bits: u8) -> Result<Self, String> {
+        let mut hll = Self::new(index_bits)?;
+        for hash in hashes {
+            hll.add_hash(*hash);
+        }
+        Ok(hll)
+    }
+
+    /// Create a new HLL set with `index_bits` from one integer per line, added with `add_u64`
+    ///
+    /// Surrounding whitespace and blank lines are ignored. Fails on the first line that is not
+    /// an unsigned integer, reporting its 1-based line number.
+    pub fn from_int_lines<R: BufRead>(reader: R, index_bits: u8) -> Result<Self, String> {
+        let mut hll = Self::new(index_bits)?;
+        for (number, line) in reader.lines().enumerate() {
+            let line = line.map_err(|e| format!("Failed to read line {}: {}", number + 1, e))?;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let value: u64 = line
+                .parse()
+                .map_err(|e| format!("Line {} is not an integer ({:?}): {}", number + 1, line, e))?;
+            hll.add_u64(value);
+        }
+        Ok(hll)
+    }
+
+    /// Create a new HLL set hashing elements with `seed`
+    ///
+    /// Sets with different seeds hash elements independently of each other and cannot be merged.
+    /// Seed 0 is the same as `new`.
+    pub fn with_seed(index_bits: u8, seed: u32) -> Result<Self, String> {
+        let mut hll = Self::new(index_bits)?;
+        hll.seed = seed;
+        Ok(hll)
+    }
+
+    /// Switch to `index_bits`, emptying the set
+    ///
+    /// The register allocation is reused when it is large enough. Seed and rank mode are kept.
+    pub fn reconfigure(&mut self, index_bits: u8) -> Result<(), String> {
+        helpers::validate_index_bits(index_bits)?;
+        self.register.clear();
+        self.register.resize(helpers::registers_from_bits(&index_bits), 0);
+        self.index_bits = index_bits;
+        self.refresh_cache();
+        Ok(())
+    }
+
+    /// Empty the set, keeping its configuration
+    pub fn clear(&mut self) {
+        self.register.fill(0);
+        self.refresh_cache();
+    }
+
+    /// Touch every page of the registers so they are resident before a hot insertion loop
+    ///
+    /// A fresh register vector is usually backed by untouched zero pages, which fault in on the
+    /// first scattered writes. Register values are left unchanged.
+    pub fn prefault(&mut self) {
+        const PAGE_SIZE: usize = 4096;
+        let start = self.register.as_ptr() as usize;
+        for offset in helpers::page_offsets(start, self.register.len(), PAGE_SIZE) {
+            self.register[offset] = std::hint::black_box(self.register[offset]);
+        }
+    }
+
+    /// Write `value` to the register at `index`, keeping the running indicator in sync
+    fn update_register(&mut self, index: usize, value: u8) {
+        let old = self.register[index];
+        if old == value {
+            return;
+        }
+        self.indicator_sum += helpers::reciprocal_power(value) - helpers::reciprocal_power(old);
+        if old == 0 {
+            self.zeros -= 1;
+        }
+        if value == 0 {
+            self.zeros += 1;
+        }
+        self.register[index] = value;
+        self.generation += 1;
+    }
+
+    /// Recompute the running indicator and zero count from the registers
+    fn refresh_cache(&mut self) {
+        self.indicator_sum = self.register.iter().map(|x| helpers::reciprocal_power(*x)).sum();
+        self.zeros = helpers::count_zeros(&self.register);
+        self.scale = self.alpha() * self.register.len().pow(2) as f64;
+        self.generation += 1;
+    }
+
+    /// Counter increasing every time the registers or estimator settings change
+    ///
+    /// An estimate computed at one generation stays valid as long as the generation is the same,
+    /// so callers can cache `(generation, count)` pairs. Adding an element that leaves every
+    /// register unchanged does not bump it.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Number of bits used as register indexes
+    pub fn index_bits(&self) -> u8 {
+        self.index_bits
+    }
+
+    /// Number of registers in the set
+    pub fn num_registers(&self) -> usize {
+        self.register.len()
+    }
+
+    /// Number of registers that are not empty, tracked as registers change
+    pub fn nonzero_count(&self) -> usize {
+        self.register.len() - self.zeros
+    }
+
+    /// Whether no element has been added to the set
+    pub fn is_empty(&self) -> bool {
+        self.zeros == self.register.len()
+    }
+
+    /// Seed used for hashing the elements
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
+
+    /// Check the invariants of the set, e.g. after loading untrusted data or `set_register`
+    ///
+    /// Validates `index_bits`, the number of registers, that every rank is reachable with
+    /// `index_bits` like `from_bytes` does, and that the running indicator matches the registers.
+    pub fn verify(&self) -> Result<(), String> {
+        check_registers(self.index_bits, &self.register)?;
+        let zeros = helpers::count_zeros(&self.register);
+        let sum: f64 = self.register.iter().map(|x| helpers::reciprocal_power(*x)).sum();
+        if zeros != self.zeros || sum != self.indicator_sum {
+            return Err("Running indicator does not match the registers".to_string());
+        }
+        Ok(())
+    }
+
+    /// Number of registers the register allocation can hold
+    pub fn capacity(&self) -> usize {
+        self.register.capacity()
+    }
+
+    /// Reserve room for `additional` more registers
+    ///
+    /// Registers are stored densely with a length fixed by `index_bits`, so this does nothing.
+    /// It exists for parity with `Vec` and storage modes that grow.
+    pub fn reserve(&mut self, additional: usize) {
+        let _ = additional;
+    }
+
+    /// Approximate number of bytes used by the set, including its heap allocations
+    pub fn memory_bytes(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.register.capacity()
+            + self.history.capacity() * std::mem::size_of::<f64>()
+    }
+
+    /// Borrow the register values
+    ///
+    /// Together with the configuration accessors this is everything `to_bytes` writes, so callers
+    /// can write the header themselves and export the registers without copying.
+    pub fn registers_as_slice(&self) -> &[u8] {
+        &self.register
+    }
+
+    /// Create a new HLL set hashing elements with `hash_algo`
+    ///
+    /// Sets with different hash functions cannot be merged.
+    pub fn with_hash_algo(index_bits: u8, hash_algo: HashAlgo) -> Result<Self, String> {
+        let mut hll = Self::new(index_bits)?;
+        hll.hash_algo = hash_algo;
+        Ok(hll)
+    }
+
+    /// Create a new HLL set keeping the last `capacity` estimates recorded with `snapshot`
+    pub fn with_history(index_bits: u8, capacity: usize) -> Result<Self, String> {
+        let mut hll = Self::new(index_bits)?;
+        hll.history = VecDeque::with_capacity(capacity);
+        hll.history_capacity = capacity;
+        Ok(hll)
+    }
+
+    /// Record the current `count()` in the history, dropping the oldest estimate when full
+    ///
+    /// Does nothing if the set was not created with `with_history`.
+    pub fn snapshot(&mut self) {
+        if self.history_capacity == 0 {
+            return;
+        }
+        if self.history.len() == self.history_capacity {
+            self.history.pop_front();
+        }
+        self.history.push_back(self.count());
+        // Keep the estimates in one slice for `history`
+        self.history.make_contiguous();
+    }
+
+    /// Recorded estimates from oldest to newest
+    pub fn history(&self) -> &[f64] {
+        self.history.as_slices().0
+    }
+
+    /// Hash function used for hashing the elements
+    pub fn hash_algo(&self) -> HashAlgo {
+        self.hash_algo
+    }
+
+    /// Mode used for computing ranks
+    pub fn rank_mode(&self) -> RankMode {
+        self.rank_mode
+    }
+
+    /// Add a new hashable element to the set
+    ///
+    /// ```
+    /// use std::collections::HashSet;
+    ///
+    /// use hll_rs::HyperLogLog;
+    ///
+    /// let mut hll = HyperLogLog::new(12).unwrap();
+    /// let mut exact = HashSet::new();
+    /// for i in 0..10_000 {
+    ///     let name = format!("user-{}", i % 3_000);
+    ///     hll.add(&name);
+    ///     exact.insert(name);
+    /// }
+    /// let error = (hll.count() - exact.len() as f64).abs() / exact.len() as f64;
+    /// assert!(error < 3.0 * hll.relative_error());
+    /// ```
+    pub fn add<T: Hash>(&mut self, value: &T) {
+        let hash = helpers::hash_value_32_seeded(value, self.seed, self.hash_algo);
+        self.add_hash(hash);
+    }
+
+    /// Add raw bytes to the set, hashing them directly with Murmur3
+    pub fn add_bytes(&mut self, bytes: &[u8]) {
+        let hash = self.hash_bytes(bytes);
+        self.add_hash(hash);
+    }
+
+    /// Add a string to the set by hashing its UTF-8 bytes
+    ///
+    /// This is the canonical path for strings shared with other languages. `add(&"abc")` goes
+    /// through `Hash`, which also writes a terminating `0xff` byte and gives a different hash.
+    pub fn add_str(&mut self, s: &str) {
+        self.add_bytes(s.as_bytes());
+    }
+
+    /// Add an integer to the set by hashing its little endian bytes
+    ///
+    /// Bypasses `Hash`, so the result does not depend on how the platform hashes integers.
+    pub fn add_u64(&mut self, value: u64) {
+        self.add_bytes(&value.to_le_bytes());
+    }
+
+    /// Add an integer to the set by hashing its little endian bytes, see `add_u64`
+    pub fn add_u128(&mut self, value: u128) {
+        self.add_bytes(&value.to_le_bytes());
+    }
+
+    /// Add an integer to the set by hashing its little endian bytes, see `add_u64`
+    pub fn add_u32(&mut self, value: u32) {
+        self.add_bytes(&value.to_le_bytes());
+    }
+
+    /// Add an integer to the set by hashing its little endian bytes, see `add_u64`
+    pub fn add_i64(&mut self, value: i64) {
+        self.add_bytes(&value.to_le_bytes());
+    }
+
+    /// Add a pair of values as one element, hashing their canonical bytes like `add_bytes`
+    ///
+    /// The bytes are the `CanonicalEncode` encoding of `a` followed by that of `b`, so the hash
+    /// is the same on every platform, e.g. `(1_u32, "x")` is `[1, 0, 0, 0]`, the length of `"x"`
+    /// as 8 little endian bytes and `b'x'`.
+    pub fn add_tuple<A, B>(&mut self, a: &A, b: &B)
+    where
+        A: CanonicalEncode + ?Sized,
+        B: CanonicalEncode + ?Sized,
+    {
+        let mut bytes = Vec::new();
+        a.encode(&mut bytes);
+        b.encode(&mut bytes);
+        self.add_bytes(&bytes);
+    }
+
+    /// Hash `bytes` with the seed and hash function of this set, as done by `add_bytes`
+    ///
+    /// This is the canonical hashing path: `add_bytes`, `add_str` and the integer adders give the
+    /// same hash on every platform, so their sets can be merged across architectures. `add`
+    /// depends on how `Hash` writes multi-byte values, which follows the native byte order.
+    pub fn hash_bytes(&self, bytes: &[u8]) -> u32 {
+        helpers::hash_bytes_32(bytes, self.seed, self.hash_algo)
+    }
+
+    /// Add integer keys to the set, hashing the little endian bytes of each like `add_bytes`
+    ///
+    /// Keys are processed in chunks whose register updates are prefetched before they are
+    /// written, which helps with the random register access at high precisions.
+    pub fn add_u32_slice(&mut self, keys: &[u32]) {
+        const CHUNK: usize = 16;
+        let mut updates: [(usize, u8); CHUNK] = [(0, 0); CHUNK];
+        for chunk in keys.chunks(CHUNK) {
+            for (update, key) in updates.iter_mut().zip(chunk.iter()) {
+                let hash = helpers::hash_bytes_32(&key.to_le_bytes(), self.seed, self.hash_algo);
+                *update = self.index_and_rank(hash);
+                helpers::prefetch(&self.register[update.0]);
+            }
+            for (register_index, zeros) in &updates[..chunk.len()] {
+                let value = cmp::max(*zeros, self.register[*register_index]);
+                self.update_register(*register_index, value);
+            }
+        }
+    }
+
+    /// Add an already hashed element to the set
+    ///
+    /// `hash` should be uniformly distributed over 32 bits for the estimates to hold.
+    pub fn add_hash(&mut self, hash: u32) {
+        let (register_index, zeros) = self.index_and_rank(hash);
+        let value = cmp::max(zeros, self.register[register_index]);
+        self.update_register(register_index, value);
+    }
+
+    /// Add a batch of already hashed elements to the set, see `add_hash`
+    ///
+    /// Ranks are computed for fixed size groups of hashes before any register is written, which
+    /// keeps the rank loop free of memory dependencies so the compiler can unroll it.
+    pub fn add_many_hashes(&mut self, hashes: &[u32]) {
+        const LANES: usize = 8;
+        let mut chunks = hashes.chunks_exact(LANES);
+        for chunk in &mut chunks {
+            let mut updates: [(usize, u8); LANES] = [(0, 0); LANES];
+            for (update, hash) in updates.iter_mut().zip(chunk.iter()) {
+                *update = self.index_and_rank(*hash);
+            }
+            for (register_index, zeros) in updates {
+                let value = cmp::max(zeros, self.register[register_index]);
+                self.update_register(register_index, value);
+            }
+        }
+        for hash in chunks.remainder() {
+            self.add_hash(*hash);
+        }
+    }
+
+    /// Split `hash` into its register index and rank
+    fn index_and_rank(&self, hash: u32) -> (usize, u8) {
+        helpers::index_and_rank(hash, self.index_bits, self.rank_mode)
+    }
+
+    /// Add all elements of `iter`, hashing each of them with `map`
+    pub fn add_iter_hashed<I, T, F>(&mut self, iter: I, map: F)
+    where
+        I: IntoIterator<Item = T>,
+        F: Fn(T) -> u32,
+    {
+        for value in iter {
+            self.add_hash(map(value));
+        }
+    }
+
+    /// Lazily add the elements of `iter`, yielding `count()` after each of them
+    ///
+    /// Consumes the set; `count` reads the running indicator, so every step is O(1) besides the
+    /// hashing. See `running_estimates` to keep the set and sample less often.
+    pub fn estimating<I, T>(mut self, iter: I) -> impl Iterator<Item = f64>
+    where
+        I: Iterator<Item = T>,
+        T: Hash,
+    {
+        iter.map(move |value| {
+            self.add(&value);
+            self.count()
+        })
+    }
+
+    /// Add all elements of `iter`, recording `(items_seen, count())` after every `every` elements
+    ///
+    /// Nothing is recorded if `every` is 0.
+    pub fn running_estimates<I, T>(&mut self, iter: I, every: usize) -> Vec<(usize, f64)>
+    where
+        I: Iterator<Item = T>,
+        T: Hash,
+    {
+        let mut estimates: Vec<(usize, f64)> = Vec::new();
+        for (i, value) in iter.enumerate() {
+            self.add(&value);
+            let seen = i + 1;
+            if every != 0 && seen.is_multiple_of(every) {
+                estimates.push((seen, self.count()));
+            }
+        }
+        estimates
+    }
+
+    /// Add an optional element to the set, `None` values are skipped
+    pub fn add_opt<T: Hash>(&mut self, value: Option<&T>) {
+        if let Some(value) = value {
+            self.add(value);
+        }
+    }
+
+    /// Merge registers of `other` into this set
+    ///
+    /// ```
+    /// use hll_rs::HyperLogLog;
+    ///
+    /// let mut a = HyperLogLog::new(12).unwrap();
+    /// let mut b = HyperLogLog::new(12).unwrap();
+    /// for i in 0..6_000_u32 {
+    ///     a.add(&i);
+    ///     b.add(&(i + 4_000));
+    /// }
+    /// a.merge(&b).unwrap();
+    /// // Union has 10 000 distinct elements
+    /// assert!((a.count() - 10_000.0).abs() / 10_000.0 < 3.0 * a.relative_error());
+    /// assert!(a.merge(&HyperLogLog::new(8).unwrap()).is_err());
+    /// ```
+    pub fn merge(&mut self, other: &HyperLogLog) -> Result<(), String> {
+        self.check_compatible(other)?;
+        self.merge_registers(&other.register)
+    }
+
+    /// Merge `other` into this set and return the increase of `count()` it caused, clamped at 0
+    ///
+    /// The increase approximates how many distinct elements of `other` were new to this set.
+    pub fn merge_tracked(&mut self, other: &HyperLogLog) -> Result<f64, String> {
+        let before = self.count();
+        self.merge(other)?;
+        Ok((self.count() - before).max(0.0))
+    }
+
+    /// Merge registers of `other` into this set, returning the set for chaining
+    pub fn merge_assign(&mut self, other: &HyperLogLog) -> Result<&mut Self, String> {
+        self.merge(other)?;
+        Ok(self)
+    }
+
+    /// Merge `other` into this set, folding whichever set has more index bits to the other's
+    ///
+    /// If `other` has fewer index bits this set is folded with `fold_to` first and permanently
+    /// loses precision. A set with more index bits is folded into a copy, leaving `other` as is.
+    pub fn try_merge(&mut self, other: &HyperLogLog) -> Result<(), String> {
+        self.check_same_hashing(other)?;
+        if other.index_bits > self.index_bits {
+            let mut folded = other.clone();
+            folded.fold_to(self.index_bits)?;
+            return self.merge(&folded);
+        }
+        self.fold_to(other.index_bits)?;
+        self.merge(other)
+    }
+
+    /// Reduce the set to `index_bits`, giving the registers it would have had with `index_bits`
+    ///
+    /// Each new register combines `2^(old - new)` old registers. The dropped index bits become
+    /// part of the rank, so the result is the same as adding the elements to a set created with
+    /// `index_bits`. The relative error grows accordingly.
+    pub fn fold_to(&mut self, index_bits: u8) -> Result<(), String> {
+        helpers::validate_index_bits(index_bits)?;
+        if index_bits > self.index_bits {
+            return Err(
+                format!(
+                    "Cannot fold {} index bits to more index bits ({})", self.index_bits, index_bits
+                )
+            );
+        }
+        let dropped: u32 = (self.index_bits - index_bits) as u32;
+        let max_rank: u8 = helpers::max_rank(self.index_bits);
+        let mut register: Vec<u8> = vec![0; helpers::registers_from_bits(&index_bits)];
+        for (index, value) in self.register.iter().enumerate() {
+            if *value == 0 {
+                continue;
+            }
+            let low = helpers::n_le_bits(&(index as u32), &dropped);
+            let rank: u8 = match self.rank_mode {
+                // All zero remaining bits continue into the dropped index bits
+                RankMode::TrailingZeros if *value == max_rank => {
+                    max_rank + (low | 1 << dropped).trailing_zeros() as u8
+                }
+                RankMode::TrailingZeros => *value,
+                // The dropped index bits now come first in the remaining bits
+                RankMode::LeadingZeros if low == 0 => *value + dropped as u8,
+                RankMode::LeadingZeros => (low << (32 - dropped)).leading_zeros() as u8 + 1,
+            };
+            let target = &mut register[index >> dropped];
+            *target = cmp::max(*target, rank);
+        }
+        self.register = register;
+        self.index_bits = index_bits;
+        self.refresh_cache();
+        Ok(())
+    }
+
+    /// Fold the set to the most index bits whose `memory_bytes` fit in `max_bytes`
+    ///
+    /// The set is left as is if it already fits. Fails without changing the set if it does not
+    /// fit even with 4 index bits.
+    pub fn shrink_to_budget(&mut self, max_bytes: usize) -> Result<(), String> {
+        let overhead = self.memory_bytes() - self.register.capacity();
+        let m = |index_bits: u8| helpers::registers_from_bits(&index_bits);
+        let index_bits = match (4..=self.index_bits).rev().find(|x| overhead + m(*x) <= max_bytes) {
+            Some(index_bits) => index_bits,
+            None => return Err(
+                format!(
+                    "Set does not fit in {} bytes (needs {} with 4 index bits)",
+                    max_bytes, overhead + m(4)
+                )
+            ),
+        };
+        if index_bits < self.index_bits {
+            self.fold_to(index_bits)?;
+        }
+        self.register.shrink_to_fit();
+        Ok(())
+    }
+
+    /// Check that registers of `other` can be combined with this set
+    fn check_compatible(&self, other: &HyperLogLog) -> Result<(), String> {
+        if self.index_bits != other.index_bits {
+            return Err(
+                format!(
+                    "Sets have different index bits ({} and {})", self.index_bits, other.index_bits
+                )
+            );
+        }
+        self.check_same_hashing(other)
+    }
+
+    /// Check that `other` hashes and ranks elements the same way as this set
+    fn check_same_hashing(&self, other: &HyperLogLog) -> Result<(), String> {
+        if self.seed != other.seed {
+            return Err(
+                format!("Sets have different seeds ({} and {})", self.seed, other.seed)
+            );
+        }
+        if self.hash_algo != other.hash_algo {
+            return Err(
+                format!(
+                    "Sets have different hash algorithms ({:?} and {:?})",
+                    self.hash_algo, other.hash_algo
+                )
+            );
+        }
+        if self.rank_mode != other.rank_mode {
+            return Err(
+                format!(
+                    "Sets have different rank modes ({:?} and {:?})",
+                    self.rank_mode, other.rank_mode
+                )
+            );
+        }
+        Ok(())
+    }
+
+    /// Check if every register of this set is at least the corresponding register of `other`
+    ///
+    /// A dominating set approximately contains all elements of `other`.
+    pub fn dominates(&self, other: &HyperLogLog) -> Result<bool, String> {
+        self.check_compatible(other)?;
+        Ok(self.register.iter().zip(other.register.iter()).all(|(a, b)| a >= b))
+    }
+
+    /// List `(index, self_value, other_value)` for every register that differs from `other`
+    pub fn register_diff(&self, other: &HyperLogLog) -> Result<Vec<(usize, u8, u8)>, String> {
+        self.check_compatible(other)?;
+        Ok(
+            self.register
+                .iter()
+                .zip(other.register.iter())
+                .enumerate()
+                .filter(|(_, (a, b))| a != b)
+                .map(|(i, (a, b))| (i, *a, *b))
+                .collect()
+        )
+    }
+
+    /// Merge raw register values into this set
+    ///
+    /// Only the number of registers is validated, the caller is responsible for the registers
+    /// coming from a compatible set.
+    pub fn merge_registers(&mut self, registers: &[u8]) -> Result<(), String> {
+        if self.register.len() != registers.len() {
+            return Err(
+                format!(
+                    "Expected {} registers (was {})", self.register.len(), registers.len()
+                )
+            );
+        }
+        for (index, other) in registers.iter().enumerate() {
+            let value = cmp::max(self.register[index], *other);
+            self.update_register(index, value);
+        }
+        Ok(())
+    }
+
+    /// Age the set by keeping `factor` of each register value
+    ///
+    /// Each register is reduced by `round(value * (1 - factor))`, so a `factor` of 1.0 leaves the
+    /// set untouched and 0.0 empties it. Combined with `merge` this gives an approximate,
+    /// time-decayed union of an old and a fresh set. This is experimental: the decayed registers
+    /// do not correspond to any real set of elements.
+    pub fn decay(&mut self, factor: f64) {
+        let factor = factor.clamp(0.0, 1.0);
+        for value in self.register.iter_mut() {
+            let reduction = (*value as f64 * (1.0 - factor)).round() as u8;
+            *value = value.saturating_sub(reduction);
+        }
+        self.refresh_cache();
+    }
+
+    /// Overwrite the register at `index` with `value`
+    pub fn set_register(&mut self, index: usize, value: u8) -> Result<(), String> {
+        let len = self.register.len();
+        if index >= len {
+            return Err(format!("Register index {} out of bounds (len {})", index, len));
+        }
+        self.update_register(index, value);
+        Ok(())
+    }
+
+    /// Raise the register at `index` to `rank` like adding an element with that rank would
+    ///
+    /// Unlike `set_register` a lower `rank` leaves the register unchanged.
+    pub fn add_register(&mut self, index: usize, rank: u8) -> Result<(), String> {
+        let len = self.register.len();
+        if index >= len {
+            return Err(format!("Register index {} out of bounds (len {})", index, len));
+        }
+        let value = cmp::max(rank, self.register[index]);
+        self.update_register(index, value);
+        Ok(())
+    }
+
+    /// Iterate over `(index, value)` pairs of the non-empty registers
+    pub fn iter_nonzero(&self) -> impl Iterator<Item = (usize, u8)> + '_ {
+        self.register
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| **x > 0)
+            .map(|(i, x)| (i, *x))
+    }
+
+    /// Export the number of registers holding each rank as a JSON object
+    ///
+    /// Keys are the ranks as strings in ascending order and values their register counts, e.g.
+    /// `{"0": 10, "1": 4, "3": 2}`. Ranks held by no register are left out, so the counts sum to
+    /// `num_registers()`.
+    pub fn to_histogram_json(&self) -> String {
+        let mut counts = [0_usize; 256];
+        for value in &self.register {
+            counts[*value as usize] += 1;
+        }
+        let entries: Vec<String> = counts
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(rank, count)| format!("\"{}\": {}", rank, count))
+            .collect();
+        format!("{{{}}}", entries.join(", "))
+    }
+
+    /// Return the `q`-th quantile of the register values, `q` is clamped to 0.0..=1.0
+    pub fn register_quantile(&self, q: f64) -> u8 {
+        let mut sorted: Vec<u8> = self.register.clone();
+        sorted.sort_unstable();
+        let position = (q.clamp(0.0, 1.0) * (sorted.len() - 1) as f64).round() as usize;
+        sorted[position]
+    }
+
+    /// Count the cardinality using `f32` arithmetic
+    ///
+    /// Same estimator as `count` but with reduced precision, the result can differ from `count`
+    /// in the last few significant digits.
+    pub fn count_f32(&self) -> f32 {
+        let m: f32 = self.register.len() as f32;
+        let estimate: f32 = self.alpha() as f32 * m * m * helpers::indicator_f32(&self.register);
+        if estimate <= 2.5 * m {
+            let zeros: usize = helpers::count_zeros(&self.register);
+            if zeros != 0 {
+                return m * (m / zeros as f32).ln();
+            }
+            estimate
+        } else if estimate <= HASH_SPACE as f32 / 30.0 {
+            estimate
+        } else {
+            -(HASH_SPACE as f32) * (1.0 - estimate / HASH_SPACE as f32).ln()
+        }
+    }
+
+    /// Content hash of the set for caching and deduplication
+    ///
+    /// Covers the registers and the configuration (index bits, seed, hash algorithm and rank
+    /// mode), so equal sets always share a fingerprint. Stable across platforms and releases.
+    pub fn fingerprint(&self) -> u64 {
+        let config: [u8; 7] = {
+            let seed = self.seed.to_le_bytes();
+            [
+                self.index_bits, seed[0], seed[1], seed[2], seed[3],
+                self.hash_algo as u8, self.rank_mode as u8,
+            ]
+        };
+        helpers::hash_parts_64(&[&config, &self.register])
+    }
+
+    /// Standard error of the estimate relative to the cardinality, `1.04 / sqrt(m)`
+    pub fn relative_error(&self) -> f64 {
+        1.04 / (self.register.len() as f64).sqrt()
+    }
+
+    /// Standard error of the estimate relative to `cardinality`, accounting for linear counting
+    ///
+    /// Below `2.5 * m` the estimate comes from linear counting, whose relative standard error is
+    /// `sqrt(m * (e^t - t - 1)) / n` with `t = n / m` (Whang et al.). It is capped at
+    /// `relative_error`, which applies to larger cardinalities.
+    pub fn expected_error_at(&self, cardinality: usize) -> f64 {
+        if cardinality == 0 {
+            return 0.0;
+        }
+        let m: f64 = self.register.len() as f64;
+        let n: f64 = cardinality as f64;
+        if n > 2.5 * m {
+            return self.relative_error();
+        }
+        let t: f64 = n / m;
+        let linear: f64 = (m * (t.exp() - t - 1.0)).sqrt() / n;
+        linear.min(self.relative_error())
+    }
+
+    /// Smallest number of index bits whose `relative_error` is at most `target_error`
+    ///
+    /// Clamped to the supported range of 4 to 16 bits.
+    pub fn bits_for_error(target_error: f64) -> u8 {
+        (4..=16)
+            .find(|bits| 1.04 / (helpers::registers_from_bits(bits) as f64).sqrt() <= target_error)
+            .unwrap_or(16)
+    }
+
+    /// Relative error bound that holds with probability `p` (e.g. 0.95)
+    ///
+    /// The bound is two-sided, `relative_error` scaled by the standard normal quantile at
+    /// `(1 + p) / 2`. Returns `None` unless `p` is strictly between 0 and 1, the bound is 0 at
+    /// 0 and unbounded at 1.
+    pub fn error_percentile(&self, p: f64) -> Option<f64> {
+        if !(p > 0.0 && p < 1.0) {
+            return None;
+        }
+        Some(helpers::normal_quantile((1.0 + p) / 2.0) * self.relative_error())
+    }
+
+    /// Serialize the set into bytes
+    ///
+    /// Layout is a format version byte, followed by `index_bits`, the little endian seed, the hash
+    /// algorithm, the rank mode, the hash width in bits and the register values. Version 1 bytes
+    /// without the hash configuration can still be read as sets with the default configuration.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::with_capacity(self.register.len() + HEADER_LEN);
+        bytes.push(FORMAT_VERSION);
+        bytes.push(self.index_bits);
+        bytes.extend_from_slice(&self.seed.to_le_bytes());
+        bytes.push(match self.hash_algo {
+            HashAlgo::Murmur3 => 0,
+            HashAlgo::Fnv => 1,
+        });
+        bytes.push(match self.rank_mode {
+            RankMode::TrailingZeros => 0,
+            RankMode::LeadingZeros => 1,
+        });
+        bytes.push(HASH_BITS);
+        bytes.extend_from_slice(&self.register);
+        bytes
+    }
+
+    /// Serialize the set into a form that is equal for equal sets
+    ///
+    /// Canonical bytes are the `to_bytes` layout of the current `FORMAT_VERSION`: the
+    /// configuration that decides how elements map to registers (index bits, seed, hash
+    /// algorithm, rank mode) followed by the registers. State that does not describe the set is
+    /// left out, i.e. the history and alpha override. Sets reaching the same
+    /// registers by adding, merging, folding or decoding older formats give the same bytes.
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        self.to_bytes()
+    }
+
+    /// Deserialize a set from bytes created with `to_bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let (header, registers) = parse_bytes(bytes)?;
+        let mut hll = Self::new(header.index_bits)?;
+        hll.seed = header.seed;
+        hll.hash_algo = header.hash_algo;
+        hll.rank_mode = header.rank_mode;
+        hll.register.copy_from_slice(registers);
+        hll.refresh_cache();
+        Ok(hll)
+    }
+
+    /// Use `alpha` as the bias correction constant instead of the computed one, `None` restores
+    /// the default
+    pub fn set_alpha_override(&mut self, alpha: Option<f64>) {
+        self.alpha_override = alpha;
+        self.scale = self.alpha() * self.register.len().pow(2) as f64;
+        self.generation += 1;
+    }
+
+    /// Estimate `alpha`
+    fn alpha(&self) -> f64 {
+        self.alpha_override.unwrap_or_else(|| helpers::alpha(self.register.len() as f64))
+    }
+
+    /// Count the cardinality of the current set with the default `EstimationMethod`
+    ///
+    /// Assumes a valid set, see `try_count` for a checked variant. The indicator sum is kept up to
+    /// date as registers change, so this does not walk the registers.
+    ///
+    /// ```
+    /// use std::collections::HashSet;
+    ///
+    /// use hll_rs::HyperLogLog;
+    ///
+    /// let mut hll = HyperLogLog::new(10).unwrap();
+    /// assert_eq!(hll.count(), 0.0);
+    /// let values: Vec<u64> = (0..5_000).map(|x| x * x % 2_000).collect();
+    /// for value in &values {
+    ///     hll.add_u64(*value);
+    /// }
+    /// let exact = values.iter().collect::<HashSet<_>>().len() as f64;
+    /// assert!((hll.count() - exact).abs() / exact < 3.0 * hll.relative_error());
+    /// ```
+    pub fn count(&self) -> f64 {
+        self.count_corrected()
+    }
+
+    /// Count the cardinality like `count`, typed as a number of elements
+    pub fn cardinality(&self) -> Cardinality {
+        Cardinality(self.count())
+    }
+
+    /// Count the cardinality, returning `None` for an empty register or a non-finite estimate
+    pub fn try_count(&self) -> Option<f64> {
+        if self.register.is_empty() {
+            return None;
+        }
+        Some(self.count()).filter(|x| x.is_finite())
+    }
+
+    /// Ratio of the estimate to the cardinality the 32 bit hash counts reliably (`2^32 / 30`)
+    ///
+    /// Past that point the large range correction kicks in as hashes start colliding, so a load
+    /// factor near or above 1 means a wider hash is needed.
+    pub fn load_factor(&self) -> f64 {
+        self.count() / (HASH_SPACE / 30.0)
+    }
+
+    /// Count the cardinality and flag whether it is in unreliable territory
+    ///
+    /// The flag is set when the `load_factor` is above 1 or the estimate is not finite.
+    pub fn count_saturating(&self) -> (f64, bool) {
+        let estimate = self.count();
+        (estimate, !estimate.is_finite() || self.load_factor() > 1.0)
+    }
+
+    /// Count the cardinality like `count`, also returning which range correction was used
+    pub fn count_explained(&self) -> (f64, Regime) {
+        helpers::correct_explained(self.count_raw(), self.register.len() as f64, self.zeros)
+    }
+
+    /// Count the cardinality of the current set with the given `method`
+    ///
+    /// Fails if `method` is not available with the precision of the set, see `count_beta`.
+    pub fn count_with_method(&self, method: EstimationMethod) -> Result<f64, String> {
+        match method {
+            EstimationMethod::Raw => Ok(self.count_raw()),
+            EstimationMethod::Corrected => Ok(self.count_corrected()),
+            EstimationMethod::Beta => self.count_beta(),
+            EstimationMethod::LogLog => Ok(self.count_loglog()),
+        }
+    }
+
+    /// Count the cardinality like `count`, using `scratch` as the table of reciprocal powers
+    ///
+    /// `scratch[i]` is overwritten with `2^-i`, registers past its length fall back to computing
+    /// the power. No allocation is done.
+    pub fn count_into_scratch(&self, scratch: &mut [f64]) -> f64 {
+        let mut value = 1.0;
+        for entry in scratch.iter_mut() {
+            *entry = value;
+            value /= 2.0;
+        }
+        let sum: f64 = self.register
+            .iter()
+            .map(|x| match scratch.get(*x as usize) {
+                Some(power) => *power,
+                None => helpers::reciprocal_power(*x),
+            })
+            .sum();
+        let raw = self.scale * (1_f64 / sum);
+        self.correct(raw, helpers::count_zeros(&self.register))
+    }
+
+    /// Count the cardinality with the LogLog-Beta estimator [3]
+    ///
+    /// A single polynomial in the number of empty registers replaces the small and large range
+    /// corrections. Coefficients are only published for 14 index bits, other precisions are an
+    /// error.
+    pub fn count_beta(&self) -> Result<f64, String> {
+        if self.index_bits != 14 {
+            return Err(format!(
+                "LogLog-Beta is only available with 14 index bits (was {})",
+                self.index_bits
+            ));
+        }
+        let m: f64 = self.register.len() as f64;
+        let zeros: f64 = self.zeros as f64;
+        Ok(self.alpha() * m * (m - zeros) / (helpers::beta_14(zeros) + self.indicator_sum))
+    }
+
+    /// Count the cardinality with the geometric mean LogLog estimator
+    ///
+    /// Uses `alpha * m * 2^mean` from the original LogLog counting algorithm on the same
+    /// registers. Its standard error is about `1.30 / sqrt(m)` against `1.04 / sqrt(m)` for
+    /// `count`, and it has no small range correction. Meant for comparisons only.
+    pub fn count_loglog(&self) -> f64 {
+        let m: f64 = self.register.len() as f64;
+        let mean: f64 = self.register.iter().map(|x| *x as f64).sum::<f64>() / m;
+        helpers::alpha_loglog(m) * m * 2_f64.powf(mean)
+    }
+
+    /// Count the cardinality without any range corrections
+    pub fn count_raw(&self) -> f64 {
+        self.scale * (1_f64 / self.indicator_sum)
+    }
+
+    /// Count the cardinality applying the small and large range corrections
+    fn count_corrected(&self) -> f64 {
+        self.correct(self.count_raw(), self.zeros)
+    }
+
+    /// Apply the small and large range corrections to a `raw` estimate
+    fn correct(&self, raw: f64, zeros: usize) -> f64 {
+        helpers::correct(raw, self.register.len() as f64, zeros)
+    }
+
+    /// Decrease of `count()` caused by zeroing each register on its own
+    pub fn register_contributions(&self) -> Vec<f64> {
+        let estimate = self.count();
+        let sum: f64 = self.indicator_sum;
+        let zeros: usize = self.zeros;
+        let scale: f64 = self.scale;
+        self.register
+            .iter()
+            .map(|x| {
+                if *x == 0 {
+                    return 0.0;
+                }
+                let zeroed_sum = sum - helpers::reciprocal_power(*x) + 1.0;
+                estimate - self.correct(scale / zeroed_sum, zeros + 1)
+            })
+            .collect()
+    }
+
+    /// Largest relative change of `count()` caused by zeroing a single register
+    pub fn perturbation_sensitivity(&self) -> f64 {
+        let estimate = self.count();
+        if estimate == 0.0 {
+            return 0.0;
+        }
+        let largest = self
+            .register_contributions()
+            .into_iter()
+            .fold(0.0, f64::max);
+        largest / estimate
+    }
+
+    /// Count the cardinality of the union with `other` without merging the sets
+    pub fn union_count(&self, other: &HyperLogLog) -> Result<f64, String> {
+        self.check_compatible(other)?;
+        let mut sum: f64 = 0.0;
+        let mut zeros: usize = 0;
+        for (a, b) in self.register.iter().zip(other.register.iter()) {
+            let value = cmp::max(*a, *b);
+            if value == 0 {
+                zeros += 1;
+            }
+            sum += helpers::reciprocal_power(value);
+        }
+        let raw = self.scale * (1_f64 / sum);
+        Ok(self.correct(raw, zeros))
+    }
+
+    /// Count the cardinality of the intersection with `other` by inclusion-exclusion
+    ///
+    /// The estimate is clamped at 0 and its error grows with the size of the union.
+    pub fn intersect_count(&self, other: &HyperLogLog) -> Result<f64, String> {
+        let union = self.union_count(other)?;
+        Ok((self.count() + other.count() - union).max(0.0))
+    }
+
+    /// Count the cardinality of elements in this set but not in `other`, clamped at 0
+    pub fn difference_count(&self, other: &HyperLogLog) -> Result<f64, String> {
+        let intersection = self.intersect_count(other)?;
+        Ok((self.count() - intersection).max(0.0))
+    }
+}
+
+/// Width of the hashes, stored in the serialized header
+const HASH_BITS: u8 = 32;
+
+/// Length of the serialized header before the registers
+const HEADER_LEN: usize = 9;
+
+/// Set configuration stored in front of the serialized registers
+struct Header {
+    index_bits: u8,
+    seed: u32,
+    hash_algo: HashAlgo,
+    rank_mode: RankMode,
+}
+
+/// Split bytes created with `HyperLogLog::to_bytes` into the header and registers
+fn parse_bytes(bytes: &[u8]) -> Result<(Header, &[u8]), String> {
+    match bytes.first() {
+        None => Err("Expected a format version byte (was empty)".to_string()),
+        Some(1) => parse_bytes_v1(&bytes[1..]),
+        Some(&FORMAT_VERSION) => parse_bytes_v2(&bytes[1..]),
+        Some(version) => Err(
+            format!(
+                "Unsupported format version {} (expected {})", version, FORMAT_VERSION
+            )
+        ),
+    }
+}
+
+/// Split version 1 bytes following the version byte, using the default hash configuration
+fn parse_bytes_v1(bytes: &[u8]) -> Result<(Header, &[u8]), String> {
+    if bytes.is_empty() {
+        return Err("Expected an index bits byte (was empty)".to_string());
+    }
+    let header = Header {
+        index_bits: bytes[0],
+        seed: 0,
+        hash_algo: HashAlgo::default(),
+        rank_mode: RankMode::default(),
+    };
+    Ok((header, check_registers(bytes[0], &bytes[1..])?))
+}
+
+/// Split version 2 bytes following the version byte
+fn parse_bytes_v2(bytes: &[u8]) -> Result<(Header, &[u8]), String> {
+    if bytes.len() < HEADER_LEN - 1 {
+        return Err(
+            format!("Expected {} header bytes (was {})", HEADER_LEN - 1, bytes.len())
+        );
+    }
+    let hash_algo = match bytes[5] {
+        0 => HashAlgo::Murmur3,
+        1 => HashAlgo::Fnv,
+        other => return Err(format!("Unsupported hash algorithm {}", other)),
+    };
+    let rank_mode = match bytes[6] {
+        0 => RankMode::TrailingZeros,
+        1 => RankMode::LeadingZeros,
+        other => return Err(format!("Unsupported rank mode {}", other)),
+    };
+    if bytes[7] != HASH_BITS {
+        return Err(format!("Unsupported hash width {} (expected {})", bytes[7], HASH_BITS));
+    }
+    let header = Header {
+        index_bits: bytes[0],
+        seed: u32::from_le_bytes([bytes[1], bytes[2], bytes[3], bytes[4]]),
+        hash_algo,
+        rank_mode,
+    };
+    Ok((header, check_registers(bytes[0], &bytes[HEADER_LEN - 1..])?))
+}
+
+/// Check that `registers` holds the number of registers for `index_bits` with reachable ranks
+///
+/// A rank above the number of remaining hash bits plus one cannot come from adding elements, so
+/// such bytes are corrupt.
+fn check_registers(index_bits: u8, registers: &[u8]) -> Result<&[u8], String> {
+    helpers::validate_index_bits(index_bits)?;
+    let m: usize = helpers::registers_from_bits(&index_bits);
+    if registers.len() != m {
+        return Err(format!("Expected {} registers (was {})", m, registers.len()));
+    }
+    let max_rank = helpers::max_rank(index_bits);
+    if let Some((index, rank)) = registers.iter().enumerate().find(|(_, x)| **x > max_rank) {
+        return Err(
+            format!(
+                "Register {} has rank {} (max {} with {} index bits)",
+                index, rank, max_rank, index_bits
+            )
+        );
+    }
+    Ok(registers)
+}
+
+/// Estimate the cardinality of a set serialized with `HyperLogLog::to_bytes`
+///
+/// Gives the same estimate as `HyperLogLog::count` without copying the registers.
+pub fn estimate_from_bytes(bytes: &[u8]) -> Result<f64, String> {
+    let (_, registers) = parse_bytes(bytes)?;
+    let m_pow_2: f64 = registers.len().pow(2) as f64;
+    let raw = helpers::alpha(registers.len() as f64) * m_pow_2 * helpers::indicator(registers);
+    Ok(helpers::correct(raw, registers.len() as f64, helpers::count_zeros(registers)))
+}
+
+/// Estimate the number of distinct `items` with a relative standard error of `target_error`
+pub fn estimate<T: Hash>(items: &[T], target_error: f64) -> f64 {
+    let mut hll = HyperLogLog::new(HyperLogLog::bits_for_error(target_error)).unwrap();
+    for item in items {
+        hll.add(item);
+    }
+    hll.count()
+}
+
+/// Merge two sets serialized with `HyperLogLog::to_bytes` into serialized bytes
+///
+/// Both sets must have the same configuration, as for `HyperLogLog::merge`.
+pub fn merge_bytes_to_bytes(a: &[u8], b: &[u8]) -> Result<Vec<u8>, String> {
+    let mut hll = HyperLogLog::from_bytes(a)?;
+    hll.merge(&HyperLogLog::from_bytes(b)?)?;
+    Ok(hll.to_bytes())
+}
+
+/// Merge all `sources` into `dest`
+///
+/// Registers are merged in blocks across all sources, so each block of `dest` stays in cache
+/// while it is maxed with every source instead of streaming `dest` once per source. All sources
+/// are checked before `dest` is modified.
+pub fn batch_merge(dest: &mut HyperLogLog, sources: &[HyperLogLog]) -> Result<(), String> {
+    const BLOCK: usize = 4096;
+    for source in sources {
+        dest.check_compatible(source)?;
+    }
+    for start in (0..dest.register.len()).step_by(BLOCK) {
+        let end = cmp::min(start + BLOCK, dest.register.len());
+        for source in sources {
+            for index in start..end {
+                let value = cmp::max(dest.register[index], source.register[index]);
+                dest.update_register(index, value);
+            }
+        }
+    }
+    Ok(())
+}
+
+impl Index<usize> for HyperLogLog {
+    type Output = u8;
+
+    /// Value of the register at `index`, panics if out of bounds
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.register[index]
+    }
+}
+
+/// Consuming iterator over `(index, value)` pairs of the non-empty registers of a HLL
+#[derive(Debug)]
+pub struct IntoNonzero {
+    registers: std::iter::Enumerate<std::vec::IntoIter<u8>>,
+}
+
+impl Iterator for IntoNonzero {
+    type Item = (usize, u8);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.registers.find(|(_, x)| *x > 0)
+    }
+}
+
+impl IntoIterator for HyperLogLog {
+    type Item = (usize, u8);
+    type IntoIter = IntoNonzero;
+
+    /// Consume the set into its non-empty registers, see `iter_nonzero`
+    fn into_iter(self) -> Self::IntoIter {
+        IntoNonzero { registers: self.register.into_iter().enumerate() }
+    }
+}
+
+impl TryFrom<&[u8]> for HyperLogLog {
+    type Error = String;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        Self::from_bytes(bytes)
+    }
+}
+
+impl From<&HyperLogLog> for Vec<u8> {
+    fn from(hll: &HyperLogLog) -> Self {
+        hll.to_bytes()
+    }
+}
+
+mod helpers {
+    use std::hash::{Hash, Hasher as _};
+
+    use hash32::Hasher;
+
+    use super::{HashAlgo, RankMode, Regime, HASH_SPACE};
+
+    /// Return a 32 bit hash of a `value` using `algo` with `seed` hashed in before it
+    ///
+    /// Seed 0 hashes only the `value`.
+    pub fn hash_value_32_seeded<T: Hash>(value: &T, seed: u32, algo: HashAlgo) -> u32 {
+        match algo {
+            HashAlgo::Murmur3 => seeded_hash::<hash32::Murmur3Hasher>(|h| value.hash(h), seed),
+            HashAlgo::Fnv => seeded_hash::<hash32::FnvHasher>(|h| value.hash(h), seed),
+        }
+    }
+
+    /// Return a 32 bit hash of raw `bytes` using `algo` with `seed` hashed in before them
+    pub fn hash_bytes_32(bytes: &[u8], seed: u32, algo: HashAlgo) -> u32 {
+        match algo {
+            HashAlgo::Murmur3 => seeded_hash::<hash32::Murmur3Hasher>(|h| h.write(bytes), seed),
+            HashAlgo::Fnv => seeded_hash::<hash32::FnvHasher>(|h| h.write(bytes), seed),
+        }
+    }
+
+    /// Return a 64 bit Murmur3 hash of the concatenated `parts`
+    pub fn hash_parts_64(parts: &[&[u8]]) -> u64 {
+        let write = |h: &mut hash32::Murmur3Hasher| parts.iter().for_each(|part| h.write(part));
+        let high = seeded_hash(write, 1) as u64;
+        let low = seeded_hash(write, 2) as u64;
+        high << 32 | low
+    }
+
+    /// Hash whatever `write` writes into a new `H` after a non-zero `seed`
+    fn seeded_hash<H: Hasher + Default>(write: impl FnOnce(&mut H), seed: u32) -> u32 {
+        let mut hasher = H::default();
+        if seed != 0 {
+            hasher.write(&seed.to_le_bytes());
+        }
+        write(&mut hasher);
+        hasher.finish32()
+    }
+
+    /// Hint the CPU to fetch `value` into the cache, a no-op on other than x86_64
+    #[inline(always)]
+    pub fn prefetch(value: &u8) {
+        #[cfg(target_arch = "x86_64")]
+        // SAFETY: prefetching has no side effects and `value` is a valid reference
+        unsafe {
+            use std::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+            _mm_prefetch(value as *const u8 as *const i8, _MM_HINT_T0);
+        }
+        #[cfg(not(target_arch = "x86_64"))]
+        let _ = value;
+    }
+
+    /// Return `n` big endian (most significant) bits of a `value`
+    pub fn n_be_bits(value: &u32, n: &u32) -> u32 {
+        let shift_amount = 32 - n;
+        value >> shift_amount
+    }
+
+    /// Return `n` least endian bits of a `value`
+    pub fn n_le_bits(value: &u32, n: &u32) -> u32 {
+        let bitmask: u32 = (1 << n) - 1;
+        value & bitmask
+    }
+
+    /// Highest rank a register can reach with `index_bits`, one past the remaining hash bits
+    pub fn max_rank(index_bits: u8) -> u8 {
+        33 - index_bits
+    }
+
+    /// Check that `index_bits` is within the supported range
+    pub fn validate_index_bits(index_bits: u8) -> Result<(), String> {
+        if !(4..=16).contains(&index_bits) {
+            return Err(
+                format!(
+                    "Number of index bits must be between 4 and 16 (was {})", index_bits
+                )
+            );
+        }
+        Ok(())
+    }
+
+    /// Offsets into a buffer at address `start` with `len` bytes touching every page it overlaps
+    ///
+    /// The buffer is usually not page aligned, so the first offset is 0 and the following ones
+    /// are at the page boundaries inside the buffer. The last byte is always included.
+    pub fn page_offsets(start: usize, len: usize, page_size: usize) -> Vec<usize> {
+        if len == 0 {
+            return Vec::new();
+        }
+        let first_page = start - start % page_size;
+        let mut offsets: Vec<usize> = (first_page..start + len)
+            .step_by(page_size)
+            .map(|page| page.max(start) - start)
+            .collect();
+        if offsets.last() != Some(&(len - 1)) {
+            offsets.push(len - 1);
+        }
+        offsets
+    }
+
+    /// Calculate number of registers based on `index_bits`
+    pub fn registers_from_bits(index_bits: &u8) -> usize {
+        2_usize.checked_pow(*index_bits as u32).unwrap()
+    }
+
+    /// `2^-rank` for every rank a valid register can hold
+    const RECIPROCAL_POWERS: [f64; 64] = {
+        let mut table = [0.0; 64];
+        let mut value = 1.0;
+        let mut rank = 0;
+        while rank < 64 {
+            table[rank] = value;
+            value /= 2.0;
+            rank += 1;
+        }
+        table
+    };
+
+    /// Return `2^-rank`, looked up from a table for valid ranks
+    pub fn reciprocal_power(rank: u8) -> f64 {
+        match RECIPROCAL_POWERS.get(rank as usize) {
+            Some(value) => *value,
+            None => 1_f64 / 2_f64.powi(rank as i32),
+        }
+    }
+
+    /// Calculate indicator Z
+    pub fn indicator(register: &[u8]) -> f64 {
+        let val: f64 = register
+            .iter()
+            .map(|x| reciprocal_power(*x))
+            .sum();
+        1_f64 / val
+    }
+
+    /// Calculate indicator Z using `f32`
+    pub fn indicator_f32(register: &[u8]) -> f32 {
+        let val: f32 = register
+            .iter()
+            .map(|x| 2_f32.powi(-(*x as i32)))
+            .sum();
+        1_f32 / val
+    }
+
+    /// Quantile function of the standard normal distribution for `p` in 0.0..1.0
+    ///
+    /// Uses the rational approximation by Peter Acklam, accurate to about 1e-9.
+    pub fn normal_quantile(p: f64) -> f64 {
+        const A: [f64; 6] = [
+            -3.969683028665376e+01, 2.209460984245205e+02, -2.759285104469687e+02,
+            1.38357751867269e+02, -3.066479806614716e+01, 2.506628277459239e+00,
+        ];
+        const B: [f64; 5] = [
+            -5.447609879822406e+01, 1.615858368580409e+02, -1.556989798598866e+02,
+            6.680131188771972e+01, -1.328068155288572e+01,
+        ];
+        const C: [f64; 6] = [
+            -7.784894002430293e-03, -3.223964580411365e-01, -2.400758277161838e+00,
+            -2.549732539343734e+00, 4.374664141464968e+00, 2.938163982698783e+00,
+        ];
+        const D: [f64; 4] = [
+            7.784695709041462e-03, 3.224671290700398e-01, 2.445134137142996e+00,
+            3.754408661907416e+00,
+        ];
+        const P_LOW: f64 = 0.02425;
+
+        let tail = |q: f64| {
+            (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+                / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+        };
+        if p < P_LOW {
+            tail((-2.0 * p.ln()).sqrt())
+        } else if p <= 1.0 - P_LOW {
+            let q = p - 0.5;
+            let r = q * q;
+            (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+                / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+        } else {
+            -tail((-2.0 * (1.0 - p).ln()).sqrt())
+        }
+    }
+
+    /// Return the `N` most significant bits of `value`, see `n_be_bits`
+    pub fn high_bits<const N: u32>(value: u32) -> u32 {
+        value >> (32 - N)
+    }
+
+    /// Return the `N` least significant bits of `value`, see `n_le_bits`
+    pub fn low_bits<const N: u32>(value: u32) -> u32 {
+        value & ((1 << N) - 1)
+    }
+
+    /// Split `hash` into its register index and rank
+    ///
+    /// Dispatches to a variant with the bit counts fixed at compile time for the supported
+    /// `index_bits`, so the hot path uses constant shifts.
+    pub fn index_and_rank(hash: u32, index_bits: u8, rank_mode: RankMode) -> (usize, u8) {
+        match index_bits {
+            4 => index_and_rank_const::<4, 28>(hash, rank_mode),
+            5 => index_and_rank_const::<5, 27>(hash, rank_mode),
+            6 => index_and_rank_const::<6, 26>(hash, rank_mode),
+            7 => index_and_rank_const::<7, 25>(hash, rank_mode),
+            8 => index_and_rank_const::<8, 24>(hash, rank_mode),
+            9 => index_and_rank_const::<9, 23>(hash, rank_mode),
+            10 => index_and_rank_const::<10, 22>(hash, rank_mode),
+            11 => index_and_rank_const::<11, 21>(hash, rank_mode),
+            12 => index_and_rank_const::<12, 20>(hash, rank_mode),
+            13 => index_and_rank_const::<13, 19>(hash, rank_mode),
+            14 => index_and_rank_const::<14, 18>(hash, rank_mode),
+            15 => index_and_rank_const::<15, 17>(hash, rank_mode),
+            16 => index_and_rank_const::<16, 16>(hash, rank_mode),
+            _ => index_and_rank_runtime(hash, index_bits, rank_mode),
+        }
+    }
+
+    /// `index_and_rank` for `N` index bits and `W = 32 - N` remaining bits
+    fn index_and_rank_const<const N: u32, const W: u32>(
+        hash: u32, rank_mode: RankMode
+    ) -> (usize, u8) {
+        debug_assert_eq!(N + W, 32);
+        let register_index = high_bits::<N>(hash) as usize;
+        // A sentinel bit right past the remaining bits caps the zeros to their width when they
+        // are all zero, without a branch
+        let zeros: u8 = match rank_mode {
+            // Count trailing zeros in remaining bits
+            RankMode::TrailingZeros => {
+                let non_index = low_bits::<W>(hash);
+                (non_index | 1 << W).trailing_zeros() as u8 + 1
+            }
+            // Count leading zeros in remaining bits, shifted to the left end of the hash
+            RankMode::LeadingZeros => {
+                let non_index = hash << N;
+                (non_index | 1 << (N - 1)).leading_zeros() as u8 + 1
+            }
+        };
+        (register_index, zeros)
+    }
+
+    /// `index_and_rank` with the bit counts only known at runtime
+    pub fn index_and_rank_runtime(hash: u32, index_bits: u8, rank_mode: RankMode) -> (usize, u8) {
+        let register_index = n_be_bits(&hash, &(index_bits as u32)) as usize;
+        let width: u32 = 32 - index_bits as u32;
+        let zeros: u8 = match rank_mode {
+            RankMode::TrailingZeros => {
+                let non_index = n_le_bits(&hash, &width);
+                (non_index | 1 << width).trailing_zeros() as u8 + 1
+            }
+            RankMode::LeadingZeros => {
+                let non_index = hash << index_bits;
+                (non_index | 1 << (index_bits - 1)).leading_zeros() as u8 + 1
+            }
+        };
+        (register_index, zeros)
+    }
+
+    /// Estimate `alpha` for `m` registers
+    pub fn alpha(m: f64) -> f64 {
+        // Added ranges of values if register lenght happens to not be a power of 2
+        if m < 32.0 {
+            0.673
+        } else if m < 64.0 {
+            0.697
+        } else if m < 128.0 {
+            0.709
+        } else {
+            0.7213 / (1.0 + 1.079 / m)
+        }
+    }
+
+    /// LogLog-Beta bias polynomial for 14 index bits and `zeros` empty registers
+    pub fn beta_14(zeros: f64) -> f64 {
+        let zl: f64 = (zeros + 1.0).ln();
+        -0.370393911 * zeros
+            + 0.070471823 * zl
+            + 0.17393686 * zl.powi(2)
+            + 0.16339839 * zl.powi(3)
+            - 0.09237745 * zl.powi(4)
+            + 0.03738027 * zl.powi(5)
+            - 0.005384159 * zl.powi(6)
+            + 0.00042419 * zl.powi(7)
+    }
+
+    /// Estimate the LogLog bias correction constant for `m` registers
+    pub fn alpha_loglog(m: f64) -> f64 {
+        let pi_2: f64 = std::f64::consts::PI.powi(2);
+        let ln_2_2: f64 = std::f64::consts::LN_2.powi(2);
+        0.39701 - (2.0 * pi_2 + ln_2_2) / (48.0 * m)
+    }
+
+    /// Apply the small and large range corrections to a `raw` estimate
+    pub fn correct(raw: f64, m: f64, zeros: usize) -> f64 {
+        correct_explained(raw, m, zeros).0
+    }
+
+    /// Apply the small and large range corrections to a `raw` estimate, returning the regime
+    pub fn correct_explained(raw: f64, m: f64, zeros: usize) -> (f64, Regime) {
+        if raw <= 2.5 * m {
+            if zeros != 0 {
+                return (linear_counting(m, zeros as f64), Regime::LinearCounting);
+            }
+            (raw, Regime::Raw)
+        } else if raw <= HASH_SPACE / 30.0 {
+            (raw, Regime::Raw)
+        } else {
+            (-HASH_SPACE * (1.0 - raw / HASH_SPACE).ln(), Regime::LargeRange)
+        }
+    }
+
+    /// Count the number of empty registers
+    pub fn count_zeros(register: &[u8]) -> usize {
+        register.iter().filter(|x| **x == 0).count()
+    }
+
+    /// Linear counting estimate with `m` registers of which `zeros` are empty
+    pub fn linear_counting(m: f64, zeros: f64) -> f64 {
+        m * (m / zeros).ln()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use rand::Rng;
+
+    use super::*;
+
+    #[test]
+    fn test_registers_from_bits() {
+        assert_eq!(helpers::registers_from_bits(&3), 8);
+    }
+
+    #[test]
+    fn test_n_be_bits() {
+        let number: u32 = 0b1010_0100_0000_0000_0000_0000_0000_0000;
+        let ret = helpers::n_be_bits(&number, &6);
+        assert_eq!(ret, 0b101001);
+    }
+
+    #[test]
+    fn test_n_le_bits() {
+        let number: u32 = 0b1010_0100;
+        let ret = helpers::n_le_bits(&number, &3);
+        assert_eq!(ret, 0b100);
+    }
+
+    #[test]
+    fn test_const_bits() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..100 {
+            let hash: u32 = rng.gen();
+            assert_eq!(helpers::high_bits::<4>(hash), helpers::n_be_bits(&hash, &4));
+            assert_eq!(helpers::high_bits::<16>(hash), helpers::n_be_bits(&hash, &16));
+            assert_eq!(helpers::low_bits::<16>(hash), helpers::n_le_bits(&hash, &16));
+            assert_eq!(helpers::low_bits::<28>(hash), helpers::n_le_bits(&hash, &28));
+            for index_bits in 4..=16 {
+                for rank_mode in [RankMode::TrailingZeros, RankMode::LeadingZeros] {
+                    assert_eq!(
+                        helpers::index_and_rank(hash, index_bits, rank_mode),
+                        helpers::index_and_rank_runtime(hash, index_bits, rank_mode)
+                    );
+                }
+            }
+        }
+        // All zero remaining bits hit the sentinel
+        for index_bits in 4..=16 {
+            let hash: u32 = u32::MAX << (32 - index_bits);
+            for rank_mode in [RankMode::TrailingZeros, RankMode::LeadingZeros] {
+                assert_eq!(
+                    helpers::index_and_rank(hash, index_bits, rank_mode),
+                    helpers::index_and_rank_runtime(hash, index_bits, rank_mode)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_hll_add() {
+        let mut hll = HyperLogLog::new(4).unwrap();
+        // Hash should equal 2766284370 = 10100100111000100010011001010010
+        hll.add(&"moros".to_string());
+        assert_eq!(hll.register, vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0])
+    }
+
+    #[test]
+    fn test_hll_macro() {
+        let hll: HyperLogLog = HLL!(6);
+        assert_eq!(hll.index_bits, 6);
+    }
+
+    #[test]
+    fn test_hll_add_opt_none() {
+        let mut hll = HyperLogLog::new(4).unwrap();
+        for _ in 0..100 {
+            hll.add_opt::<u32>(None);
+        }
+        assert!(hll.register.iter().all(|x| *x == 0));
+    }
+
+    #[test]
+    fn test_hll_add_opt_mixed() {
+        let mut expected = HyperLogLog::new(8).unwrap();
+        let mut hll = HyperLogLog::new(8).unwrap();
+        for i in 0..1000_u32 {
+            if i % 3 == 0 {
+                hll.add_opt::<u32>(None);
+            } else {
+                hll.add_opt(Some(&i));
+                expected.add(&i);
+            }
+        }
+        assert_eq!(hll.register, expected.register);
+        assert_eq!(hll.count(), expected.count());
+    }
+
+    #[test]
+    fn test_count_zeros() {
+        assert_eq!(helpers::count_zeros(&[0, 1, 0, 3]), 2);
+    }
+
+    #[test]
+    fn test_count_raw_method() {
+        let mut hll = HyperLogLog::new(8).unwrap();
+        for i in 0..1000_u32 {
+            hll.add(&i);
+        }
+        assert_eq!(hll.count_with_method(EstimationMethod::Raw), Ok(hll.count_raw()));
+    }
+
+    #[test]
+    fn test_count_methods() {
+        let mut hll = HyperLogLog::new(10).unwrap();
+        assert_eq!(hll.count_with_method(EstimationMethod::Corrected), Ok(0.0));
+        for i in 0..10_000_u32 {
+            hll.add(&i);
+        }
+        for method in [EstimationMethod::Raw, EstimationMethod::Corrected, EstimationMethod::LogLog]
+        {
+            let estimate = hll.count_with_method(method).unwrap();
+            assert!((estimate - 10_000.0).abs() / 10_000.0 < 0.1, "{:?}: {}", method, estimate);
+        }
+        assert_eq!(hll.count_with_method(EstimationMethod::Corrected), Ok(hll.count()));
+        assert_eq!(hll.count_with_method(EstimationMethod::LogLog), Ok(hll.count_loglog()));
+        assert_eq!(hll.count_with_method(EstimationMethod::Beta), hll.count_beta());
+        assert!(hll.count_with_method(EstimationMethod::Beta).is_err());
+
+        let mut beta = HyperLogLog::new(14).unwrap();
+        for i in 0..10_000_u32 {
+            beta.add(&i);
+        }
+        assert_eq!(beta.count_with_method(EstimationMethod::Beta), beta.count_beta());
+        assert!(beta.count_with_method(EstimationMethod::Beta).is_ok());
+    }
+
+    #[test]
+    fn test_count_small_range() {
+        let mut hll = HyperLogLog::new(10).unwrap();
+        for i in 0..100_u32 {
+            hll.add(&i);
+        }
+        let error = (hll.count() - 100.0).abs() / 100.0;
+        assert!(error < 0.05, "{}", error);
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut a = HyperLogLog::new(8).unwrap();
+        let mut b = HyperLogLog::new(8).unwrap();
+        let mut expected = HyperLogLog::new(8).unwrap();
+        for i in 0..1000_u32 {
+            a.add(&i);
+            expected.add(&i);
+        }
+        for i in 500..2000_u32 {
+            b.add(&i);
+            expected.add(&i);
+        }
+        a.merge(&b).unwrap();
+        assert_eq!(a.register, expected.register);
+    }
+
+    #[test]
+    fn test_merge_index_bits_mismatch() {
+        let mut a = HyperLogLog::new(8).unwrap();
+        let b = HyperLogLog::new(6).unwrap();
+        assert!(a.merge(&b).is_err());
+    }
+
+    #[test]
+    fn test_decay() {
+        let mut hll = HyperLogLog::new(6).unwrap();
+        for i in 0..1000_u32 {
+            hll.add(&i);
+        }
+        let original = hll.register.clone();
+        hll.decay(1.0);
+        assert_eq!(hll.register, original);
+        hll.decay(0.5);
+        assert!(hll.register.iter().zip(original.iter()).all(|(new, old)| new <= old));
+        hll.decay(0.0);
+        assert!(hll.register.iter().all(|x| *x == 0));
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let mut hll = HyperLogLog::new(6).unwrap();
+        for i in 0..1000_u32 {
+            hll.add(&i);
+        }
+        let bytes = hll.to_bytes();
+        assert_eq!(bytes.len(), HEADER_LEN + 64);
+        let decoded = HyperLogLog::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.index_bits, 6);
+        assert_eq!(decoded.register, hll.register);
+    }
+
+    #[test]
+    fn test_bytes_conversions() {
+        let mut hll = HyperLogLog::new(5).unwrap();
+        hll.add(&"moros".to_string());
+        let bytes: Vec<u8> = (&hll).into();
+        let decoded = HyperLogLog::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(decoded.register, hll.register);
+    }
+
+    #[test]
+    fn test_bytes_malformed() {
+        assert!(HyperLogLog::try_from([1_u8].as_slice()).is_err());
+        assert!(HyperLogLog::try_from([1_u8, 4, 0, 0].as_slice()).is_err());
+        assert!(HyperLogLog::try_from([1_u8, 2, 0, 0, 0, 0].as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_num_registers() {
+        for index_bits in 4..=16 {
+            let hll = HyperLogLog::new(index_bits).unwrap();
+            assert_eq!(hll.index_bits(), index_bits);
+            assert_eq!(hll.num_registers(), 1 << index_bits);
+        }
+    }
+
+    #[test]
+    fn test_seeded_hash() {
+        let murmur = HashAlgo::Murmur3;
+        assert_eq!(helpers::hash_value_32_seeded(&"moros", 0, murmur), 2766284370);
+        assert_ne!(
+            helpers::hash_value_32_seeded(&"moros", 1, murmur),
+            helpers::hash_value_32_seeded(&"moros", 2, murmur)
+        );
+    }
+
+    #[test]
+    fn test_merge_seed_mismatch() {
+        let mut a = HyperLogLog::with_seed(8, 1).unwrap();
+        let b = HyperLogLog::with_seed(8, 2).unwrap();
+        assert!(a.merge(&b).is_err());
+    }
+
+    #[test]
+    fn test_register_quantile() {
+        let mut hll = HyperLogLog::new(4).unwrap();
+        hll.register = vec![5, 1, 3, 0, 7, 2, 2, 4, 6, 1, 0, 3, 9, 2, 8, 4];
+        assert_eq!(hll.register_quantile(0.0), 0);
+        assert_eq!(hll.register_quantile(0.5), 3);
+        assert_eq!(hll.register_quantile(1.0), 9);
+        assert_eq!(hll.register_quantile(2.0), 9);
+    }
+
+    #[test]
+    fn test_count_f32() {
+        let mut hll = HyperLogLog::new(12).unwrap();
+        for i in 0..100_000_u32 {
+            hll.add(&i);
+        }
+        let difference = (hll.count_f32() as f64 - hll.count()).abs() / hll.count();
+        assert!(difference < 1e-4, "{}", difference);
+    }
+
+    #[test]
+    fn test_iter_nonzero() {
+        let mut hll = HyperLogLog::new(8).unwrap();
+        for i in 0..100_u32 {
+            hll.add(&i);
+        }
+        let nonzero: Vec<(usize, u8)> = hll.iter_nonzero().collect();
+        assert_eq!(nonzero.len(), hll.register.len() - helpers::count_zeros(&hll.register));
+        assert!(nonzero.iter().all(|(i, x)| hll.register[*i] == *x && *x > 0));
+    }
+
+    #[test]
+    fn test_hll_add_str() {
+        let mut from_str = HyperLogLog::new(8).unwrap();
+        let mut from_bytes = HyperLogLog::new(8).unwrap();
+        let mut from_hash = HyperLogLog::new(8).unwrap();
+        for s in ["abc", "moros", "hyperloglog"] {
+            from_str.add_str(s);
+            from_bytes.add_bytes(s.as_bytes());
+            from_hash.add(&s);
+        }
+        assert_eq!(from_str.register, from_bytes.register);
+        assert_ne!(from_str.register, from_hash.register);
+    }
+
+    #[test]
+    fn test_max_precision() {
+        let mut hll = HyperLogLog::new(16).unwrap();
+        assert_eq!(hll.register.len(), 65536);
+        assert!((hll.alpha() - 0.7213 / (1.0 + 1.079 / 65536.0)).abs() < f64::EPSILON);
+        let n = 500_000_u32;
+        for i in 0..n {
+            hll.add(&i);
+        }
+        let error = (hll.count() - n as f64).abs() / n as f64;
+        assert!(error < 0.02, "{}", error);
+    }
+
+    #[test]
+    fn test_merge_registers() {
+        let mut a = HyperLogLog::new(4).unwrap();
+        let mut b = HyperLogLog::new(4).unwrap();
+        for i in 0..20_u32 {
+            a.add(&i);
+        }
+        let registers: Vec<u8> = vec![3, 0, 1, 9, 0, 0, 2, 5, 1, 1, 0, 4, 7, 0, 2, 3];
+        b.register = registers.clone();
+        let mut merged = HyperLogLog::new(4).unwrap();
+        merged.merge(&a).unwrap();
+        merged.merge(&b).unwrap();
+        a.merge_registers(&registers).unwrap();
+        assert_eq!(a.register, merged.register);
+        assert!(a.merge_registers(&[0; 8]).is_err());
+    }
+
+    #[test]
+    fn test_union_count() {
+        let mut a = HyperLogLog::new(10).unwrap();
+        let mut b = HyperLogLog::new(10).unwrap();
+        for i in 0..3000_u32 {
+            a.add(&i);
+        }
+        for i in 2000..8000_u32 {
+            b.add(&i);
+        }
+        let mut merged = a.clone();
+        merged.merge(&b).unwrap();
+        assert_eq!(a.union_count(&b).unwrap(), merged.count());
+        assert!(a.union_count(&HyperLogLog::new(8).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_rank_modes() {
+        let mut trailing = HyperLogLog::new(10).unwrap();
+        let mut leading = HyperLogLog::with_rank_mode(10, RankMode::LeadingZeros).unwrap();
+        assert_eq!(leading.rank_mode(), RankMode::LeadingZeros);
+        let n = 20_000_u32;
+        for i in 0..n {
+            trailing.add(&i);
+            leading.add(&i);
+        }
+        assert_ne!(trailing.register, leading.register);
+        for hll in [&trailing, &leading] {
+            let error = (hll.count() - n as f64).abs() / n as f64;
+            assert!(error < 0.1, "{:?}: {}", hll.rank_mode(), error);
+        }
+        let difference = (trailing.count() - leading.count()).abs() / n as f64;
+        assert!(difference < 0.1, "{}", difference);
+        assert!(trailing.merge(&leading).is_err());
+    }
+
+    #[test]
+    fn test_leading_zeros_rank() {
+        let mut hll = HyperLogLog::with_rank_mode(4, RankMode::LeadingZeros).unwrap();
+        // After the index bits 1010 the hash continues with 0100..., one leading zero
+        hll.add_hash(0b1010_0100_0000_0000_0000_0000_0000_0000);
+        assert_eq!(hll.register[10], 2);
+        // All zero remaining bits are capped to the remaining width
+        hll.add_hash(0b0001_0000_0000_0000_0000_0000_0000_0000);
+        assert_eq!(hll.register[1], 29);
+    }
+
+    #[test]
+    fn test_bytes_unsupported_version() {
+        let mut bytes = HyperLogLog::new(4).unwrap().to_bytes();
+        bytes[0] = 42;
+        assert_eq!(
+            HyperLogLog::from_bytes(&bytes).unwrap_err(),
+            format!("Unsupported format version 42 (expected {})", FORMAT_VERSION)
+        );
+        assert!(HyperLogLog::from_bytes(&[]).is_err());
+    }
+
+    #[test]
+    fn test_normal_quantile() {
+        assert!(helpers::normal_quantile(0.5).abs() < 1e-9);
+        assert!((helpers::normal_quantile(0.975) - 1.959964).abs() < 1e-6);
+        assert!((helpers::normal_quantile(0.01) + 2.326348).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_error_percentile() {
+        let hll = HyperLogLog::new(10).unwrap();
+        assert!((hll.relative_error() - 1.04 / 32.0).abs() < f64::EPSILON);
+        let ratio_95 = hll.error_percentile(0.95).unwrap() / hll.relative_error();
+        let ratio_99 = hll.error_percentile(0.99).unwrap() / hll.relative_error();
+        assert!((ratio_95 - 1.96).abs() < 0.01, "{}", ratio_95);
+        assert!((ratio_99 - 2.576).abs() < 0.01, "{}", ratio_99);
+        assert!(hll.error_percentile(1e-9).unwrap() < 1e-6);
+        assert!(hll.error_percentile(1.0 - 1e-9).unwrap().is_finite());
+        for p in [0.0, 1.0, -0.5, 1.5, f64::NAN] {
+            assert_eq!(hll.error_percentile(p), None, "{}", p);
+        }
+    }
+
+    #[test]
+    fn test_estimate_from_bytes() {
+        let mut hll = HyperLogLog::new(9).unwrap();
+        for i in 0..5000_u32 {
+            hll.add(&i);
+        }
+        let bytes = hll.to_bytes();
+        let expected = HyperLogLog::from_bytes(&bytes).unwrap().count();
+        assert_eq!(estimate_from_bytes(&bytes).unwrap(), expected);
+        assert!(estimate_from_bytes(&bytes[..10]).is_err());
+    }
+
+    #[test]
+    fn test_register_index() {
+        let mut hll = HyperLogLog::new(4).unwrap();
+        hll.add(&"moros".to_string());
+        assert_eq!(hll[10], 2);
+        hll.set_register(3, 7).unwrap();
+        assert_eq!(hll[3], 7);
+        assert!(hll.set_register(16, 1).is_err());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_register_index_out_of_bounds() {
+        let hll = HyperLogLog::new(4).unwrap();
+        let _ = hll[16];
+    }
+
+    #[test]
+    fn test_add_iter_hashed() {
+        let mut hll = HyperLogLog::new(4).unwrap();
+        // Index is the top 4 bits and the lowest set bit gives the rank
+        hll.add_iter_hashed([1_u32, 3, 3, 12], |x| x << 28 | x);
+        let mut expected = vec![0; 16];
+        expected[1] = 1;
+        expected[3] = 1;
+        expected[12] = 3;
+        assert_eq!(hll.register, expected);
+    }
+
+    #[test]
+    fn test_register_contributions() {
+        let mut hll = HyperLogLog::new(6).unwrap();
+        for i in 0..500_u32 {
+            hll.add(&i);
+        }
+        let contributions = hll.register_contributions();
+        for index in [0, 17, 40] {
+            let mut zeroed = hll.clone();
+            zeroed.set_register(index, 0).unwrap();
+            assert!((contributions[index] - (hll.count() - zeroed.count())).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_perturbation_sensitivity() {
+        let mut dominated = HyperLogLog::new(8).unwrap();
+        dominated.set_register(5, 20).unwrap();
+        let mut balanced = HyperLogLog::new(8).unwrap();
+        for index in 0..256 {
+            balanced.set_register(index, 4).unwrap();
+        }
+        assert!(dominated.perturbation_sensitivity() > balanced.perturbation_sensitivity());
+        assert_eq!(HyperLogLog::new(8).unwrap().perturbation_sensitivity(), 0.0);
+    }
+
+    #[test]
+    fn test_add_integers() {
+        for value in [0_u64, 1, 42, u32::MAX as u64, u64::MAX] {
+            let mut a = HyperLogLog::new(8).unwrap();
+            let mut b = HyperLogLog::new(8).unwrap();
+            a.add_u64(value);
+            b.add_u64(value);
+            assert_eq!(a.register, b.register);
+            b.add_bytes(&value.to_le_bytes());
+            assert_eq!(a.register, b.register);
+            let mut c = HyperLogLog::new(8).unwrap();
+            c.add_u128(value as u128);
+            c.add_bytes(&(value as u128).to_le_bytes());
+            assert_eq!(helpers::count_zeros(&c.register), 255);
+        }
+    }
+
+    #[test]
+    fn test_merge_bytes_to_bytes() {
+        let mut a = HyperLogLog::new(8).unwrap();
+        let mut b = HyperLogLog::new(8).unwrap();
+        for i in 0..1500_u32 {
+            a.add(&i);
+        }
+        for i in 1000..3000_u32 {
+            b.add(&i);
+        }
+        let merged_bytes = merge_bytes_to_bytes(&a.to_bytes(), &b.to_bytes()).unwrap();
+        let merged = HyperLogLog::from_bytes(&merged_bytes).unwrap();
+        assert_eq!(merged.count(), a.union_count(&b).unwrap());
+        let other = HyperLogLog::new(6).unwrap().to_bytes();
+        assert!(merge_bytes_to_bytes(&a.to_bytes(), &other).is_err());
+    }
+
+    #[test]
+    fn test_low_precision_max_rank() {
+        for index_bits in [4, 5] {
+            let max_rank = 33 - index_bits;
+            for rank_mode in [RankMode::TrailingZeros, RankMode::LeadingZeros] {
+                let mut hll = HyperLogLog::with_rank_mode(index_bits, rank_mode).unwrap();
+                // Remaining bits all zero
+                hll.add_hash(0xffff_ffff << (32 - index_bits));
+                assert_eq!(hll.register[hll.register.len() - 1], max_rank);
+                for i in 0..100_000_u32 {
+                    hll.add(&i);
+                }
+                assert!(hll.register.iter().all(|x| *x <= max_rank));
+            }
+        }
+    }
+
+    #[test]
+    fn test_low_precision_count() {
+        for index_bits in [4, 5] {
+            let mut hll = HyperLogLog::new(index_bits).unwrap();
+            let n = 100_000_u32;
+            for i in 0..n {
+                hll.add(&i);
+            }
+            let error = (hll.count() - n as f64).abs() / n as f64;
+            // Allow three standard errors
+            assert!(error < 3.0 * hll.relative_error(), "{}: {}", index_bits, error);
+        }
+    }
+
+    #[test]
+    fn test_intersect_and_difference_count() {
+        let mut superset = HyperLogLog::new(12).unwrap();
+        let mut subset = HyperLogLog::new(12).unwrap();
+        for i in 0..20_000_u32 {
+            superset.add(&i);
+            if i < 15_000 {
+                subset.add(&i);
+            }
+        }
+        let intersection = superset.intersect_count(&subset).unwrap();
+        assert!((intersection - 15_000.0).abs() / 15_000.0 < 0.1, "{}", intersection);
+        let difference = superset.difference_count(&subset).unwrap();
+        assert!((difference - 5_000.0).abs() / 5_000.0 < 0.2, "{}", difference);
+        assert_eq!(subset.difference_count(&superset).unwrap(), 0.0);
+        assert!(superset.difference_count(&HyperLogLog::new(4).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_registers_as_slice() {
+        let mut hll = HyperLogLog::new(7).unwrap();
+        hll.add(&"moros".to_string());
+        let registers = hll.registers_as_slice();
+        assert_eq!(registers.len(), hll.num_registers());
+        assert_eq!(registers, &hll.to_bytes()[HEADER_LEN..]);
+    }
+
+    #[test]
+    fn test_bits_for_error() {
+        assert_eq!(HyperLogLog::bits_for_error(1.0), 4);
+        assert_eq!(HyperLogLog::bits_for_error(0.02), 12);
+        assert_eq!(HyperLogLog::bits_for_error(0.0001), 16);
+        assert!(HyperLogLog::new(12).unwrap().relative_error() <= 0.02);
+        assert!(HyperLogLog::new(11).unwrap().relative_error() > 0.02);
+    }
+
+    #[test]
+    fn test_estimate() {
+        let items: Vec<u32> = (0..60_000).map(|x| x % 50_000).collect();
+        let exact = items.iter().collect::<HashSet<_>>().len() as f64;
+        let error = (estimate(&items, 0.02) - exact).abs() / exact;
+        assert!(error < 0.02, "{}", error);
+    }
+
+    #[test]
+    fn test_dominates() {
+        let mut a = HyperLogLog::new(8).unwrap();
+        let mut b = HyperLogLog::new(8).unwrap();
+        for i in 0..1000_u32 {
+            a.add(&i);
+        }
+        for i in 800..2000_u32 {
+            b.add(&i);
+        }
+        let mut merged = a.clone();
+        merged.merge(&b).unwrap();
+        assert!(merged.dominates(&a).unwrap());
+        assert!(merged.dominates(&b).unwrap());
+        assert!(!a.dominates(&merged).unwrap());
+        assert!(a.dominates(&HyperLogLog::new(4).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_count_saturating() {
+        let mut hll = HyperLogLog::new(8).unwrap();
+        for i in 0..1000_u32 {
+            hll.add(&i);
+        }
+        assert!(!hll.count_saturating().1);
+        for rank in [20, 28] {
+            for index in 0..256 {
+                hll.set_register(index, rank).unwrap();
+            }
+            assert!(hll.count_saturating().1, "{}", rank);
+        }
+    }
+
+    #[test]
+    fn test_load_factor() {
+        let mut hll = HyperLogLog::new(8).unwrap();
+        assert_eq!(hll.load_factor(), 0.0);
+        for i in 0..1000_u32 {
+            hll.add(&i);
+        }
+        assert!(hll.load_factor() < 1e-4, "{}", hll.load_factor());
+        // Heavily loaded, every register close to the largest rank
+        for index in 0..256 {
+            hll.set_register(index, 22).unwrap();
+        }
+        assert!(hll.load_factor() > 1.0, "{}", hll.load_factor());
+    }
+
+    #[test]
+    fn test_reconfigure() {
+        let mut hll = HyperLogLog::new(10).unwrap();
+        for i in 0..1000_u32 {
+            hll.add(&i);
+        }
+        let capacity = hll.register.capacity();
+        hll.reconfigure(6).unwrap();
+        assert_eq!(hll.index_bits(), 6);
+        assert_eq!(hll.num_registers(), 64);
+        assert_eq!(hll.count(), 0.0);
+        assert_eq!(hll.register.capacity(), capacity);
+        hll.reconfigure(12).unwrap();
+        assert_eq!(hll.num_registers(), 4096);
+        assert_eq!(hll.count(), 0.0);
+        assert!(hll.reconfigure(17).is_err());
+        assert_eq!(hll.index_bits(), 12);
+    }
+
+    #[test]
+    fn test_hash_algos() {
+        let n = 20_000_u32;
+        let mut registers: Vec<Vec<u8>> = Vec::new();
+        for algo in [HashAlgo::Murmur3, HashAlgo::Fnv] {
+            let mut hll = HyperLogLog::with_hash_algo(10, algo).unwrap();
+            assert_eq!(hll.hash_algo(), algo);
+            for i in 0..n {
+                hll.add_str(&format!("element-{}", i));
+            }
+            let error = (hll.count() - n as f64).abs() / n as f64;
+            assert!(error < 3.0 * hll.relative_error(), "{:?}: {}", algo, error);
+            registers.push(hll.register);
+        }
+        assert_ne!(registers[0], registers[1]);
+        let mut murmur = HyperLogLog::new(10).unwrap();
+        assert!(murmur.merge(&HyperLogLog::with_hash_algo(10, HashAlgo::Fnv).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_mismatched_hash_algos() {
+        let mut murmur = HyperLogLog::new(10).unwrap();
+        let mut fnv = HyperLogLog::with_hash_algo(10, HashAlgo::Fnv).unwrap();
+        murmur.add_str("moros");
+        fnv.add_str("moros");
+        let expected = "Sets have different hash algorithms (Murmur3 and Fnv)";
+        assert_eq!(murmur.clone().merge(&fnv).unwrap_err(), expected);
+        assert_eq!(murmur.union_count(&fnv).unwrap_err(), expected);
+        assert_eq!(murmur.intersect_count(&fnv).unwrap_err(), expected);
+        assert_eq!(murmur.difference_count(&fnv).unwrap_err(), expected);
+        assert_eq!(murmur.dominates(&fnv).unwrap_err(), expected);
+        assert_eq!(murmur.register_diff(&fnv).unwrap_err(), expected);
+        assert_eq!(batch_merge(&mut murmur.clone(), &[fnv.clone()]).unwrap_err(), expected);
+        assert_eq!(murmur.clone().into_readonly().merged(&fnv).unwrap_err(), expected);
+    }
+
+    #[test]
+    fn test_history() {
+        let mut hll = HyperLogLog::with_history(8, 3).unwrap();
+        let mut estimates: Vec<f64> = Vec::new();
+        for i in 0..5_u32 {
+            for j in 0..100_u32 {
+                hll.add(&(i * 100 + j));
+            }
+            hll.snapshot();
+            estimates.push(hll.count());
+        }
+        assert_eq!(hll.history(), &estimates[2..]);
+
+        let mut without = HyperLogLog::new(8).unwrap();
+        without.snapshot();
+        assert!(without.history().is_empty());
+    }
+
+    #[test]
+    fn test_merge_assign() -> Result<(), String> {
+        let mut sets: Vec<HyperLogLog> = Vec::new();
+        let mut expected = HyperLogLog::new(10)?;
+        for i in 0..3_u32 {
+            let mut hll = HyperLogLog::new(10)?;
+            for j in 0..1000_u32 {
+                hll.add(&(i * 1000 + j));
+                expected.add(&(i * 1000 + j));
+            }
+            sets.push(hll);
+        }
+        let mut hll = HyperLogLog::new(10)?;
+        let count = hll
+            .merge_assign(&sets[0])?
+            .merge_assign(&sets[1])?
+            .merge_assign(&sets[2])?
+            .count();
+        assert_eq!(count, expected.count());
+        Ok(())
+    }
+
+    #[test]
+    fn test_incremental_indicator() {
+        let check = |hll: &HyperLogLog| {
+            let sum: f64 = hll.register.iter().map(|x| helpers::reciprocal_power(*x)).sum();
+            assert_eq!(hll.indicator_sum, sum);
+            assert_eq!(hll.zeros, helpers::count_zeros(&hll.register));
+            assert_eq!(hll.count_raw(), helpers::alpha(hll.register.len() as f64)
+                * hll.register.len().pow(2) as f64 * helpers::indicator(&hll.register));
+        };
+        let mut rng = rand::thread_rng();
+        let mut hll = HyperLogLog::new(6).unwrap();
+        check(&hll);
+        for _ in 0..200 {
+            match rng.gen_range(0..6) {
+                0 => hll.add(&rng.gen::<u64>()),
+                1 => hll.add_u32_slice(&[rng.gen(), rng.gen(), rng.gen()]),
+                2 => {
+                    let mut other = HyperLogLog::new(6).unwrap();
+                    for _ in 0..rng.gen_range(0..100) {
+                        other.add(&rng.gen::<u32>());
+                    }
+                    hll.merge(&other).unwrap();
+                }
+                3 => hll.set_register(rng.gen_range(0..64), rng.gen_range(0..20)).unwrap(),
+                4 => hll.decay(rng.gen()),
+                _ => if rng.gen_bool(0.1) { hll.clear() },
+            }
+            check(&hll);
+        }
+        hll.reconfigure(8).unwrap();
+        check(&hll);
+        let bytes = hll.to_bytes();
+        hll.add(&1_u32);
+        check(&HyperLogLog::from_bytes(&bytes).unwrap());
+    }
+
+    #[test]
+    fn test_prefault() {
+        let mut hll = HyperLogLog::new(16).unwrap();
+        hll.prefault();
+        assert_eq!(hll.count(), 0.0);
+        assert!(hll.iter_nonzero().next().is_none());
+        let mut expected = HyperLogLog::new(16).unwrap();
+        for i in 0..10_000_u32 {
+            hll.add(&i);
+            expected.add(&i);
+        }
+        hll.prefault();
+        assert_eq!(hll.count(), expected.count());
+
+        // A buffer starting 3456 bytes into a page overlaps 17 pages
+        let offsets = helpers::page_offsets(8192 + 3456, 1 << 16, 4096);
+        assert_eq!(offsets.len(), 18);
+        assert_eq!(&offsets[..3], &[0, 640, 4736]);
+        assert_eq!(offsets[16], 640 + 15 * 4096);
+        assert_eq!(offsets[17], (1 << 16) - 1);
+        assert_eq!(helpers::page_offsets(4096, 8192, 4096), [0, 4096, 8191]);
+        assert_eq!(helpers::page_offsets(100, 10, 4096), [0, 9]);
+        assert!(helpers::page_offsets(100, 0, 4096).is_empty());
+    }
+
+    #[test]
+    fn test_batch_merge() {
+        let sources: Vec<HyperLogLog> = (0..5_u32)
+            .map(|k| {
+                let mut hll = HyperLogLog::new(14).unwrap();
+                for i in 0..10_000_u32 {
+                    hll.add(&(k * 10_000 + i));
+                }
+                hll
+            })
+            .collect();
+        let mut sequential = HyperLogLog::new(14).unwrap();
+        for source in &sources {
+            sequential.merge(source).unwrap();
+        }
+        let mut batched = HyperLogLog::new(14).unwrap();
+        batch_merge(&mut batched, &sources).unwrap();
+        assert_eq!(batched.register, sequential.register);
+        assert_eq!(batched.count(), sequential.count());
+
+        let mut mixed = sources.clone();
+        mixed.push(HyperLogLog::new(12).unwrap());
+        let mut dest = HyperLogLog::new(14).unwrap();
+        assert!(batch_merge(&mut dest, &mixed).is_err());
+        assert_eq!(dest.count(), 0.0);
+    }
+
+    #[test]
+    fn test_is_empty() {
+        let mut hll = HyperLogLog::new(8).unwrap();
+        assert!(hll.is_empty());
+        assert_eq!(hll.nonzero_count(), 0);
+        assert_eq!(hll.count(), 0.0);
+        for i in 0..100_u32 {
+            hll.add(&i);
+            assert_eq!(hll.nonzero_count(), hll.iter_nonzero().count());
+        }
+        assert!(!hll.is_empty());
+        hll.set_register(0, 0).unwrap();
+        assert_eq!(hll.nonzero_count(), hll.iter_nonzero().count());
+        hll.clear();
+        assert!(hll.is_empty());
+        assert_eq!(hll.nonzero_count(), 0);
+        assert_eq!(hll.count(), 0.0);
+    }
+
+    #[test]
+    fn test_try_count() {
+        let mut hll = HyperLogLog::new(8).unwrap();
+        hll.add(&1_u32);
+        assert_eq!(hll.try_count(), Some(hll.count()));
+        hll.register = Vec::new();
+        hll.refresh_cache();
+        assert!(hll.count().is_nan());
+        assert_eq!(hll.try_count(), None);
+    }
+
+    #[test]
+    fn test_register_diff() {
+        let mut a = HyperLogLog::new(4).unwrap();
+        for i in 0..50_u32 {
+            a.add(&i);
+        }
+        let mut b = a.clone();
+        assert!(a.register_diff(&b).unwrap().is_empty());
+        b.set_register(2, 30).unwrap();
+        b.set_register(11, 0).unwrap();
+        assert_eq!(
+            a.register_diff(&b).unwrap(), vec![(2, a[2], 30), (11, a[11], 0)]
+        );
+        assert!(a.register_diff(&HyperLogLog::new(5).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_alpha_override() {
+        let mut hll = HyperLogLog::new(6).unwrap();
+        for i in 0..10_000_u32 {
+            hll.add(&i);
+        }
+        let default = hll.count();
+        let alpha = hll.alpha();
+        hll.set_alpha_override(Some(alpha * 2.0));
+        assert!((hll.count() / default - 2.0).abs() < 1e-9);
+        hll.set_alpha_override(None);
+        assert_eq!(hll.count(), default);
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let mut a = HyperLogLog::new(8).unwrap();
+        let mut b = HyperLogLog::new(8).unwrap();
+        for i in 0..500_u32 {
+            a.add(&i);
+            b.add(&i);
+        }
+        assert_eq!(a.fingerprint(), b.fingerprint());
+        // Adding an element to an empty set always sets a register
+        let mut c = HyperLogLog::new(8).unwrap();
+        let empty = c.fingerprint();
+        c.add(&"moros");
+        assert_ne!(c.fingerprint(), empty);
+        assert_ne!(HyperLogLog::with_seed(8, 1).unwrap().fingerprint(), empty);
+    }
+
+    #[test]
+    fn test_from_hashes() {
+        let items: Vec<String> = (0..2000).map(|x| format!("item-{}", x)).collect();
+        let hashes: Vec<u32> = items
+            .iter()
+            .map(|x| helpers::hash_value_32_seeded(x, 0, HashAlgo::Murmur3))
+            .collect();
+        let mut expected = HyperLogLog::new(9).unwrap();
+        for item in &items {
+            expected.add(item);
+        }
+        let hll = HyperLogLog::from_hashes(&hashes, 9).unwrap();
+        assert_eq!(hll.register, expected.register);
+        assert!(HyperLogLog::from_hashes(&hashes, 3).is_err());
+    }
+
+    #[test]
+    fn test_running_estimates() {
+        let mut hll = HyperLogLog::new(10).unwrap();
+        let estimates = hll.running_estimates(0..1000_u32, 100);
+        assert_eq!(estimates.len(), 10);
+        assert_eq!(estimates[0].0, 100);
+        assert_eq!(*estimates.last().unwrap(), (1000, hll.count()));
+        assert!(hll.running_estimates(0..10_u32, 0).is_empty());
+    }
+
+    #[test]
+    fn test_new_unchecked() {
+        for index_bits in 4..=16 {
+            let unchecked = HyperLogLog::new_unchecked(index_bits);
+            let checked = HyperLogLog::new(index_bits).unwrap();
+            assert_eq!(format!("{:?}", unchecked), format!("{:?}", checked));
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Number of index bits must be between 4 and 16 (was 17)")]
+    fn test_new_unchecked_debug_assert() {
+        HyperLogLog::new_unchecked(17);
+    }
+
+    #[test]
+    fn test_reciprocal_power() {
+        for rank in 0..=u8::MAX {
+            assert_eq!(helpers::reciprocal_power(rank), 1_f64 / 2_f64.powi(rank as i32));
+        }
+    }
+
+    #[test]
+    fn test_indicator_table() {
+        let mut hll = HyperLogLog::new(14).unwrap();
+        for i in 0..200_000_u32 {
+            hll.add(&i);
+        }
+        let sum: f64 = hll.register.iter().map(|x| 1_f64 / 2_f64.powi(*x as i32)).sum();
+        let expected = hll.alpha() * hll.register.len().pow(2) as f64 / sum;
+        assert!((hll.count_raw() - expected).abs() / expected < 1e-12);
+    }
+
+    #[test]
+    fn test_index_and_rank_matches_reference() {
+        let mut generator = rand::thread_rng();
+        for index_bits in [4, 10, 16] {
+            for rank_mode in [RankMode::TrailingZeros, RankMode::LeadingZeros] {
+                let hll = HyperLogLog::with_rank_mode(index_bits, rank_mode).unwrap();
+                let width = 32 - index_bits as u32;
+                let mut hashes: Vec<u32> = (0..100_000).map(|_| generator.gen()).collect();
+                hashes.extend([0, u32::MAX, 1, 1 << 31]);
+                for hash in hashes {
+                    let index = (hash >> width) as usize;
+                    let zeros = match rank_mode {
+                        RankMode::TrailingZeros => {
+                            let non_index = hash & ((1 << width) - 1);
+                            cmp::min(non_index.trailing_zeros(), width) as u8 + 1
+                        }
+                        RankMode::LeadingZeros => {
+                            cmp::min((hash << index_bits).leading_zeros(), width) as u8 + 1
+                        }
+                    };
+                    assert_eq!(hll.index_and_rank(hash), (index, zeros), "{:#034b}", hash);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_add_u32_slice() {
+        let mut generator = rand::thread_rng();
+        let keys: Vec<u32> = (0..10_007).map(|_| generator.gen()).collect();
+        let mut hll = HyperLogLog::new(16).unwrap();
+        hll.add_u32_slice(&keys);
+        let mut expected = HyperLogLog::new(16).unwrap();
+        for key in &keys {
+            expected.add_bytes(&key.to_le_bytes());
+        }
+        assert_eq!(hll.register, expected.register);
+    }
+
+    #[test]
+    fn test_add_many_hashes() {
+        let mut generator = rand::thread_rng();
+        for rank_mode in [RankMode::TrailingZeros, RankMode::LeadingZeros] {
+            let hashes: Vec<u32> = (0..1_003).map(|_| generator.gen()).collect();
+            let mut hll = HyperLogLog::with_rank_mode(10, rank_mode).unwrap();
+            hll.add_many_hashes(&hashes);
+            let mut expected = HyperLogLog::with_rank_mode(10, rank_mode).unwrap();
+            for hash in &hashes {
+                expected.add_hash(*hash);
+            }
+            assert_eq!(hll.register, expected.register);
+            assert_eq!(hll.count(), expected.count());
+        }
+    }
+
+    #[test]
+    fn test_generation() {
+        let mut hll = HyperLogLog::new(8).unwrap();
+        let mut generation = hll.generation();
+        hll.add(&"moros");
+        assert!(hll.generation() > generation);
+        generation = hll.generation();
+        hll.add(&"moros");
+        hll.count();
+        assert_eq!(hll.generation(), generation);
+
+        let mut other = HyperLogLog::new(8).unwrap();
+        other.add(&"nyx");
+        hll.merge(&other).unwrap();
+        assert!(hll.generation() > generation);
+        generation = hll.generation();
+        hll.merge(&other).unwrap();
+        assert_eq!(hll.generation(), generation);
+
+        let cached = (hll.generation(), hll.count());
+        hll.clear();
+        assert!(hll.generation() > cached.0);
+        generation = hll.generation();
+        hll.set_alpha_override(Some(0.7));
+        assert!(hll.generation() > generation);
+    }
+
+    #[test]
+    fn test_count_beta() {
+        let mut hll = HyperLogLog::new(14).unwrap();
+        assert_eq!(hll.count_beta(), Ok(0.0));
+        let mut beta_error = 0.0;
+        let mut count_error = 0.0;
+        let mut added: u32 = 0;
+        for cardinality in [10, 100, 1_000, 5_000, 20_000, 50_000, 100_000, 500_000] {
+            while added < cardinality {
+                hll.add(&added);
+                added += 1;
+            }
+            let exact = cardinality as f64;
+            assert_ne!(hll.count_beta().unwrap(), hll.count());
+            beta_error += (hll.count_beta().unwrap() - exact).abs() / exact;
+            count_error += (hll.count() - exact).abs() / exact;
+        }
+        assert!(beta_error <= count_error, "{} > {}", beta_error, count_error);
+
+        for index_bits in [4, 10, 16] {
+            let mut other = HyperLogLog::new(index_bits).unwrap();
+            other.add(&1_u32);
+            assert_eq!(
+                other.count_beta().unwrap_err(),
+                format!("LogLog-Beta is only available with 14 index bits (was {})", index_bits)
+            );
+        }
+    }
+
+    #[test]
+    fn test_cached_scale() {
+        let expected = |hll: &HyperLogLog| hll.alpha() * hll.register.len().pow(2) as f64;
+        for index_bits in 4..=16 {
+            let hll = HyperLogLog::new(index_bits).unwrap();
+            assert_eq!(hll.scale, expected(&hll));
+        }
+        let mut hll = HyperLogLog::new(8).unwrap();
+        hll.reconfigure(12).unwrap();
+        assert_eq!(hll.scale, expected(&hll));
+        hll.set_alpha_override(Some(0.5));
+        assert_eq!(hll.scale, 0.5 * 4096.0 * 4096.0);
+        hll.set_alpha_override(None);
+        assert_eq!(hll.scale, expected(&hll));
+    }
+
+    #[test]
+    fn test_count_loglog() {
+        let n = 100_000_u32;
+        let mut loglog_error = 0.0;
+        let mut count_error = 0.0;
+        for seed in 0..8 {
+            let mut hll = HyperLogLog::with_seed(8, seed).unwrap();
+            for i in 0..n {
+                hll.add(&i);
+            }
+            let error = (hll.count_loglog() - n as f64).abs() / n as f64;
+            assert!(error < 4.0 * 1.30 / 16.0, "{}", error);
+            loglog_error += error;
+            count_error += (hll.count() - n as f64).abs() / n as f64;
+        }
+        assert!(loglog_error > count_error, "{} <= {}", loglog_error, count_error);
+    }
+
+    #[test]
+    fn test_fold_to() {
+        for rank_mode in [RankMode::TrailingZeros, RankMode::LeadingZeros] {
+            let mut hll = HyperLogLog::with_rank_mode(12, rank_mode).unwrap();
+            let mut expected = HyperLogLog::with_rank_mode(8, rank_mode).unwrap();
+            for i in 0..20_000_u32 {
+                hll.add(&i);
+                expected.add(&i);
+            }
+            // Hashes with all zero remaining bits exercise the rank carry into the index bits
+            for hash in [0, 0x0010_0000, 0xfff0_0000, 0x0000_0001, 0x8000_0000] {
+                hll.add_hash(hash);
+                expected.add_hash(hash);
+            }
+            hll.fold_to(8).unwrap();
+            assert_eq!(hll.index_bits(), 8);
+            assert_eq!(hll.register, expected.register);
+            assert_eq!(hll.count(), expected.count());
+        }
+        let mut hll = HyperLogLog::new(8).unwrap();
+        assert!(hll.fold_to(10).is_err());
+        assert!(hll.fold_to(3).is_err());
+        hll.fold_to(8).unwrap();
+        assert_eq!(hll.index_bits(), 8);
+    }
+
+    #[test]
+    fn test_try_merge() {
+        let mut a = HyperLogLog::new(10).unwrap();
+        let mut b = HyperLogLog::new(8).unwrap();
+        for i in 0..20_000_u32 {
+            a.add(&i);
+            b.add(&(i + 10_000));
+        }
+        let mut c = a.clone();
+        c.try_merge(&b).unwrap();
+        assert_eq!(c.index_bits(), 8);
+        b.try_merge(&a).unwrap();
+        assert_eq!(b.index_bits(), 8);
+        assert_eq!(b.register, c.register);
+        let error = (c.count() - 30_000.0).abs() / 30_000.0;
+        assert!(error < 3.0 * c.relative_error(), "{}", error);
+
+        let mut seeded = HyperLogLog::with_seed(10, 1).unwrap();
+        assert!(seeded.try_merge(&HyperLogLog::new(8).unwrap()).is_err());
+        assert_eq!(seeded.index_bits(), 10);
+    }
+
+    #[test]
+    fn test_canonical_hashes() {
+        let hll = HyperLogLog::new(8).unwrap();
+        // Reference Murmur3 x86 32 bit values with seed 0
+        assert_eq!(hll.hash_bytes(b""), 0);
+        assert_eq!(hll.hash_bytes(b"hello"), 0x248bfa47);
+        assert_eq!(hll.hash_bytes(b"The quick brown fox jumps over the lazy dog"), 0x2e4ff723);
+        assert_eq!(hll.hash_bytes(&[1, 0, 0, 0]), 0xfbf1402a);
+        assert_eq!(hll.hash_bytes(&[1, 0, 0, 0, 0, 0, 0, 0]), 0x53075d44);
+        assert_eq!(hll.hash_bytes(&[0xff; 8]), 0x627564e8);
+
+        let add = |f: &dyn Fn(&mut HyperLogLog)| {
+            let mut hll = HyperLogLog::new(8).unwrap();
+            f(&mut hll);
+            hll.register
+        };
+        let single = |hash: u32| add(&|x: &mut HyperLogLog| x.add_hash(hash));
+        assert_eq!(add(&|x: &mut HyperLogLog| x.add_u32(1)), single(0xfbf1402a));
+        assert_eq!(add(&|x: &mut HyperLogLog| x.add_u64(1)), single(0x53075d44));
+        assert_eq!(add(&|x: &mut HyperLogLog| x.add_i64(-1)), single(0x627564e8));
+        assert_eq!(add(&|x: &mut HyperLogLog| x.add_str("hello")), single(0x248bfa47));
+    }
+
+    #[test]
+    fn test_add_register() {
+        let mut hll = HyperLogLog::new(10).unwrap();
+        for index in 0..1024 {
+            hll.add_register(index, 5).unwrap();
+            hll.add_register(index, 3).unwrap();
+        }
+        assert!(hll.register.iter().all(|x| *x == 5));
+        // Every register at rank 5 gives alpha * m * 2^5, above the small range
+        let expected = helpers::alpha(1024.0) * 1024.0 * 32.0;
+        assert!((hll.count() - expected).abs() < 1e-6, "{}", hll.count());
+
+        // Half of the registers empty falls back to linear counting
+        let mut hll = HyperLogLog::new(10).unwrap();
+        for index in (0..1024).step_by(2) {
+            hll.add_register(index, 1).unwrap();
+        }
+        let expected = 1024.0 * 2_f64.ln();
+        assert!((hll.count() - expected).abs() < 1e-6, "{}", hll.count());
+        assert!(hll.add_register(1024, 1).is_err());
+    }
+
+    #[test]
+    fn test_bytes_hash_config() {
+        let mut hll = HyperLogLog::with_seed(8, 0xdead_beef).unwrap();
+        hll.hash_algo = HashAlgo::Fnv;
+        hll.rank_mode = RankMode::LeadingZeros;
+        for i in 0..1000_u32 {
+            hll.add(&i);
+        }
+        let bytes = hll.to_bytes();
+        assert_eq!(bytes.len(), HEADER_LEN + 256);
+        let restored = HyperLogLog::from_bytes(&bytes).unwrap();
+        assert_eq!(restored.seed(), 0xdead_beef);
+        assert_eq!(restored.hash_algo(), HashAlgo::Fnv);
+        assert_eq!(restored.rank_mode(), RankMode::LeadingZeros);
+        assert_eq!(restored.register, hll.register);
+
+        let plain = HyperLogLog::new(8).unwrap();
+        assert!(restored.clone().merge(&plain).is_err());
+        assert!(merge_bytes_to_bytes(&bytes, &plain.to_bytes()).is_err());
+        assert!(merge_bytes_to_bytes(&bytes, &bytes).is_ok());
+
+        let mut corrupt = bytes.clone();
+        corrupt[8] = 16;
+        assert_eq!(
+            HyperLogLog::from_bytes(&corrupt).unwrap_err(),
+            "Unsupported hash width 16 (expected 32)"
+        );
+        corrupt[7] = 9;
+        assert!(HyperLogLog::from_bytes(&corrupt).is_err());
+    }
+
+    #[test]
+    fn test_bytes_v1() {
+        let mut bytes: Vec<u8> = vec![1, 4];
+        bytes.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0]);
+        let hll = HyperLogLog::from_bytes(&bytes).unwrap();
+        let mut expected = HyperLogLog::new(4).unwrap();
+        expected.add(&"moros".to_string());
+        assert_eq!(hll.register, expected.register);
+        assert_eq!(hll.seed(), 0);
+        assert_eq!(estimate_from_bytes(&bytes).unwrap(), expected.count());
+    }
+
+    #[test]
+    fn test_count_explained() {
+        let mut hll = HyperLogLog::new(4).unwrap();
+        assert_eq!(hll.count_explained(), (0.0, Regime::LinearCounting));
+        hll.add(&"moros");
+        assert_eq!(hll.count_explained().1, Regime::LinearCounting);
+        for index in 0..16 {
+            hll.add_register(index, 5).unwrap();
+        }
+        assert_eq!(hll.count_explained(), (hll.count(), Regime::Raw));
+        // alpha * 16 * 2^25 is past 2^32 / 30
+        for index in 0..16 {
+            hll.add_register(index, 25).unwrap();
+        }
+        let (estimate, regime) = hll.count_explained();
+        assert_eq!(regime, Regime::LargeRange);
+        assert_eq!(estimate, hll.count());
+        assert!(estimate > hll.count_raw());
+    }
+
+    #[test]
+    fn test_to_histogram_json() {
+        assert_eq!(HyperLogLog::new(4).unwrap().to_histogram_json(), "{\"0\": 16}");
+        let mut hll = HyperLogLog::new(10).unwrap();
+        for i in 0..2000_u32 {
+            hll.add(&i);
+        }
+        let json = hll.to_histogram_json();
+        let inner = json.strip_prefix('{').unwrap().strip_suffix('}').unwrap();
+        let mut total = 0;
+        let mut previous: Option<u8> = None;
+        for entry in inner.split(", ") {
+            let (rank, count) = entry.split_once(": ").unwrap();
+            let rank: u8 = rank.trim_matches('"').parse().unwrap();
+            let count: usize = count.parse().unwrap();
+            assert_eq!(count, hll.register.iter().filter(|x| **x == rank).count());
+            assert!(previous.is_none_or(|x| x < rank));
+            previous = Some(rank);
+            total += count;
+        }
+        assert_eq!(total, hll.num_registers());
+    }
+
+    #[test]
+    fn test_expected_error_at() {
+        let hll = HyperLogLog::new(10).unwrap();
+        assert_eq!(hll.expected_error_at(0), 0.0);
+        for cardinality in [1, 10, 100, 1_000] {
+            assert!(hll.expected_error_at(cardinality) < hll.relative_error(), "{}", cardinality);
+        }
+        assert!(hll.expected_error_at(10) < hll.expected_error_at(1_000));
+        assert_eq!(hll.expected_error_at(100_000), hll.relative_error());
+
+        // Empirical error at 500 elements over several seeds stays close to the prediction
+        let n = 500_u32;
+        let mut squared = 0.0;
+        for seed in 0..20 {
+            let mut hll = HyperLogLog::with_seed(10, seed).unwrap();
+            for i in 0..n {
+                hll.add(&i);
+            }
+            squared += ((hll.count() - n as f64) / n as f64).powi(2);
+        }
+        let empirical = (squared / 20.0).sqrt();
+        assert!(empirical < 2.0 * hll.expected_error_at(n as usize), "{}", empirical);
+    }
+
+    #[test]
+    fn test_canonical_bytes() {
+        let mut direct = HyperLogLog::with_history(8, 4).unwrap();
+        for i in 0..3000_u32 {
+            direct.add(&i);
+            if i % 1000 == 0 {
+                direct.snapshot();
+            }
+        }
+        direct.set_alpha_override(Some(0.7));
+
+        let mut folded = HyperLogLog::new(11).unwrap();
+        let mut other = HyperLogLog::new(11).unwrap();
+        for i in 0..3000_u32 {
+            if i % 2 == 0 {
+                folded.add(&i);
+            } else {
+                other.add(&i);
+            }
+        }
+        folded.merge(&other).unwrap();
+        folded.fold_to(8).unwrap();
+        assert_eq!(folded.canonical_bytes(), direct.canonical_bytes());
+
+        let mut v1: Vec<u8> = vec![1, 8];
+        v1.extend_from_slice(direct.registers_as_slice());
+        let decoded = HyperLogLog::from_bytes(&v1).unwrap();
+        assert_eq!(decoded.canonical_bytes(), direct.canonical_bytes());
+
+        let mut copied = HyperLogLog::new(8).unwrap();
+        for (index, value) in direct.iter_nonzero() {
+            copied.set_register(index, value).unwrap();
+        }
+        assert_eq!(copied.canonical_bytes(), direct.canonical_bytes());
+    }
+
+    #[test]
+    fn test_into_iter() {
+        let mut hll = HyperLogLog::new(8).unwrap();
+        for i in 0..100_u32 {
+            hll.add(&i);
+        }
+        let expected: Vec<(usize, u8)> = hll.iter_nonzero().collect();
+        let registers = hll.register.clone();
+        let entries: Vec<(usize, u8)> = hll.into_iter().collect();
+        assert_eq!(entries, expected);
+        let mut rebuilt = HyperLogLog::new(8).unwrap();
+        for (index, value) in entries {
+            rebuilt.set_register(index, value).unwrap();
+        }
+        assert_eq!(rebuilt.register, registers);
+        assert_eq!(HyperLogLog::new(4).unwrap().into_iter().count(), 0);
+    }
+
+    #[test]
+    fn test_bytes_rank_out_of_range() {
+        let mut hll = HyperLogLog::new(4).unwrap();
+        hll.set_register(3, 29).unwrap();
+        assert!(HyperLogLog::from_bytes(&hll.to_bytes()).is_ok());
+        hll.set_register(3, 200).unwrap();
+        let bytes = hll.to_bytes();
+        assert_eq!(
+            HyperLogLog::from_bytes(&bytes).unwrap_err(),
+            "Register 3 has rank 200 (max 29 with 4 index bits)"
+        );
+        assert!(estimate_from_bytes(&bytes).is_err());
+        assert!(HyperLogLog::try_from(&bytes[..]).is_err());
+    }
+
+    #[test]
+    fn test_merge_tracked() {
+        let mut a = HyperLogLog::new(12).unwrap();
+        let mut b = HyperLogLog::new(12).unwrap();
+        for i in 0..10_000_u32 {
+            a.add(&i);
+            b.add(&(i + 5_000));
+        }
+        let before = a.count();
+        let contribution = a.merge_tracked(&b).unwrap();
+        assert_eq!(contribution, a.count() - before);
+        let error = (contribution - 5_000.0).abs() / 5_000.0;
+        assert!(error < 0.1, "{}", contribution);
+        assert_eq!(a.merge_tracked(&b).unwrap(), 0.0);
+        assert!(a.merge_tracked(&HyperLogLog::new(10).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_memory_bytes() {
+        let small = HyperLogLog::new(4).unwrap();
+        let large = HyperLogLog::new(14).unwrap();
+        assert_eq!(large.memory_bytes() - small.memory_bytes(), (1 << 14) - 16);
+        let history = HyperLogLog::with_history(4, 10).unwrap();
+        assert_eq!(history.memory_bytes() - small.memory_bytes(), 80);
+    }
+
+    #[test]
+    fn test_add_tuple() {
+        let mut hll = HyperLogLog::new(8).unwrap();
+        let bytes = [1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, b'x'];
+        assert_eq!(hll.hash_bytes(&bytes), 0x4f6b8f3d);
+        hll.add_tuple(&1_u32, "x");
+        let mut expected = HyperLogLog::new(8).unwrap();
+        expected.add_hash(0x4f6b8f3d);
+        assert_eq!(hll.register, expected.register);
+
+        let mut same = HyperLogLog::new(8).unwrap();
+        same.add_tuple(&1_u32, &"x".to_string());
+        assert_eq!(same.register, hll.register);
+    }
+
+    #[test]
+    fn test_shrink_to_budget() {
+        let mut hll = HyperLogLog::new(14).unwrap();
+        for i in 0..50_000_u32 {
+            hll.add(&i);
+        }
+        let budget = 5_000;
+        hll.shrink_to_budget(budget).unwrap();
+        assert!(hll.memory_bytes() <= budget);
+        assert_eq!(hll.index_bits(), 12);
+        let error = (hll.count() - 50_000.0).abs() / 50_000.0;
+        assert!(error < 3.0 * hll.relative_error(), "{}", error);
+
+        hll.shrink_to_budget(1_000_000).unwrap();
+        assert_eq!(hll.index_bits(), 12);
+        assert!(hll.shrink_to_budget(10).is_err());
+        assert_eq!(hll.index_bits(), 12);
+    }
+
+    #[test]
+    fn test_estimating() {
+        let mut expected = HyperLogLog::new(10).unwrap();
+        for i in 0..5_000_u32 {
+            expected.add(&i);
+        }
+        let estimates: Vec<f64> = HyperLogLog::new(10).unwrap().estimating(0..5_000_u32).collect();
+        assert_eq!(estimates.len(), 5_000);
+        assert_eq!(estimates[4_999], expected.count());
+        assert!(estimates[0] > 0.0);
+
+        // Only the consumed elements are added
+        let mut lazy = HyperLogLog::new(10).unwrap().estimating(0..5_000_u32);
+        let mut first_100 = HyperLogLog::new(10).unwrap();
+        for i in 0..100_u32 {
+            first_100.add(&i);
+        }
+        assert_eq!(lazy.nth(99), Some(first_100.count()));
+    }
+
+    #[test]
+    fn test_capacity() {
+        let mut hll = HyperLogLog::new(8).unwrap();
+        for i in 0..1000_u32 {
+            hll.add(&i);
+        }
+        assert_eq!(hll.capacity(), 256);
+        let estimate = hll.count();
+        hll.reserve(1024);
+        assert_eq!(hll.capacity(), 256);
+        assert_eq!(hll.count(), estimate);
+    }
+
+    #[test]
+    fn test_verify() {
+        let mut hll = HyperLogLog::new(6).unwrap();
+        for i in 0..500_u32 {
+            hll.add(&i);
+        }
+        hll.fold_to(5).unwrap();
+        assert_eq!(hll.verify(), Ok(()));
+
+        let mut corrupt = hll.clone();
+        corrupt.set_register(2, 40).unwrap();
+        assert_eq!(
+            corrupt.verify().unwrap_err(),
+            "Register 2 has rank 40 (max 28 with 5 index bits)"
+        );
+
+        let mut corrupt = hll.clone();
+        corrupt.index_bits = 17;
+        assert_eq!(
+            corrupt.verify().unwrap_err(),
+            "Number of index bits must be between 4 and 16 (was 17)"
+        );
+
+        let mut corrupt = hll.clone();
+        corrupt.register.pop();
+        assert_eq!(corrupt.verify().unwrap_err(), "Expected 32 registers (was 31)");
+
+        let mut corrupt = hll.clone();
+        corrupt.register[0] = corrupt.register[0].wrapping_add(1);
+        assert_eq!(
+            corrupt.verify().unwrap_err(),
+            "Running indicator does not match the registers"
+        );
+    }
+
+    #[test]
+    fn test_from_int_lines() {
+        let input = "3\n1\n\n  2 \n3\n1\n";
+        let hll = HyperLogLog::from_int_lines(input.as_bytes(), 8).unwrap();
+        let mut expected = HyperLogLog::new(8).unwrap();
+        for value in [1, 2, 3] {
+            expected.add_u64(value);
+        }
+        assert_eq!(hll.registers_as_slice(), expected.registers_as_slice());
+        assert_eq!(hll.count().round(), 3.0);
+
+        let input = "1\n2\n\nx3\n";
+        assert_eq!(
+            HyperLogLog::from_int_lines(input.as_bytes(), 8).unwrap_err(),
+            "Line 4 is not an integer (\"x3\"): invalid digit found in string"
+        );
+        assert!(HyperLogLog::from_int_lines("1".as_bytes(), 3).is_err());
+    }
+
+    #[test]
+    fn test_cardinality() {
+        let mut small = HyperLogLog::new(8).unwrap();
+        let mut large = HyperLogLog::new(8).unwrap();
+        for i in 0..1000_u32 {
+            large.add(&i);
+            if i < 10 {
+                small.add(&i);
+            }
+        }
+        assert_eq!(f64::from(large.cardinality()), large.count());
+        assert_eq!(u64::from(large.cardinality()), large.count().round() as u64);
+        assert!(small.cardinality() < large.cardinality());
+        assert_eq!(HyperLogLog::new(8).unwrap().cardinality(), Cardinality(0.0));
+
+        assert_eq!(format!("{:.1}", Cardinality(12.34)), "12.3");
+        assert_eq!(Cardinality(2.5).to_string(), "2.5");
+        assert_eq!(u64::from(Cardinality(2.5)), 3);
+        assert_eq!(u64::from(Cardinality(-1.0)), 0);
+        assert_eq!(u64::from(Cardinality(f64::NAN)), 0);
+    }
+
+    #[test]
+    fn test_count_into_scratch() {
+        let mut hll = HyperLogLog::new(10).unwrap();
+        for i in 0..50_000_u32 {
+            hll.add(&i);
+        }
+        let mut scratch = [0.0; 64];
+        assert_eq!(hll.count_into_scratch(&mut scratch), hll.count());
+        let mut short = [0.0; 4];
+        assert_eq!(hll.count_into_scratch(&mut short), hll.count());
+        assert_eq!(hll.count_into_scratch(&mut []), hll.count());
+    }
+}
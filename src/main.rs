@@ -1,149 +1,115 @@
-//! Implementation of HyperLogLog
-//!
-//! [1]: https://algo.inria.fr/flajolet/Publications/FlFuGaMe07.pdf
-//! [2]: https://en.wikipedia.org/wiki/HyperLogLog
-
-use std::cmp;
 use std::collections::HashSet;
-use std::hash::Hash;
+use std::io::{self, BufRead};
+use std::process::ExitCode;
 
-use clap::Parser;
-use indicatif::{ProgressIterator, ProgressFinish};
-use rand::Rng;
+use clap::{Parser, ValueEnum};
+use indicatif::{ProgressBar, ProgressFinish, ProgressIterator};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
-#[derive(Debug)]
-struct HyperLogLog {
-    register: Vec<u8>,
-    index_bits: u8
-}
+use hll_rs::{EstimationMethod, HyperLogLog};
 
-impl Default for HyperLogLog {
-    /// Creates a HyperLogLog with 4 bits as `index_bits`
-    fn default() -> Self {
-        Self::new(4).unwrap()
-    }
+#[derive(Parser)]
+struct Cli {
+    /// Number of index bits to use in HLL
+    #[arg(default_value_t = 6)]
+    index_bits: u8,
+    /// Exponent of 10 for numbers to randomize
+    #[arg(default_value_t = 8)]
+    numbers_exp: u32,
+    /// Exponent of 10 for maximum randomized number
+    #[arg(default_value_t = 12)]
+    max_value_exp: u32,
+    /// Print the register values after building the HLL
+    #[arg(long)]
+    output_registers: bool,
+    /// Count distinct lines read from stdin instead of random numbers
+    #[arg(long, conflicts_with = "compare")]
+    stdin: bool,
+    /// Run a number of trials and report the distribution of the error
+    #[arg(long, value_name = "TRIALS", value_parser = clap::value_parser!(u32).range(1..))]
+    compare: Option<u32>,
+    /// Seed for the random numbers, making runs reproducible
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Estimator used for the reported cardinality
+    #[arg(long, value_enum, default_value_t = Method::Corrected)]
+    method: Method,
+    /// Number of decimals of the estimate and error percentages
+    #[arg(long, default_value_t = 2)]
+    decimals: usize,
+    /// Layout of the results
+    #[arg(long, value_enum, default_value_t = Format::Human)]
+    format: Format
 }
 
-/// Create a `HyperLogLog` with a number of index bits
-macro_rules! HLL {
-    ($index_bits:expr) => {
-        HyperLogLog::new($index_bits).unwrap()
-    };
+/// Output layouts of the results
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// Labelled lines meant for reading
+    Human,
+    /// A single JSON object
+    Json,
+    /// `metric,value` rows
+    Csv,
 }
 
-impl HyperLogLog {
-    /// Create a new HyperLogLog(HLL) set with first `index_bits` used as register indexes
-    fn new(index_bits: u8) -> Result<Self, String> {
-        if !(4..=16).contains(&index_bits) {
-            return Err(
-                format!(
-                    "Number of index bits must be more than 0 and less than 9 (was {})", index_bits
-                )
-            );
+/// Print `metrics` as a JSON object or CSV rows, values must already be valid JSON
+fn print_metrics(metrics: &[(&str, String)], format: Format) {
+    match format {
+        Format::Human => unreachable!("human output is printed by the callers"),
+        Format::Json => {
+            let entries: Vec<String> =
+                metrics.iter().map(|(name, value)| format!("\"{}\": {}", name, value)).collect();
+            println!("{{{}}}", entries.join(", "));
         }
-        let m: usize = helpers::registers_from_bits(&index_bits);
-        Ok(Self { register: vec![0; m], index_bits })
-    }
-
-    /// Add a new hashable element to the set
-    fn add<T: Hash>(&mut self, value: &T) {
-        let hash = helpers::hash_value_32(value);
-        let register_index: usize =
-            helpers::n_be_bits(&hash, &(self.index_bits as u32))
-            .try_into()
-            .unwrap();
-        // Count trailing zeros in remaining bits
-        let non_index = helpers::n_le_bits(&hash, &(32 - self.index_bits as u32));
-        let zeros: u8 = non_index.trailing_zeros() as u8 + 1;
-        self.register[register_index] = cmp::max(zeros, self.register[register_index]);
-    }
-
-    /// Estimate `alpha`
-    fn alpha(&self) -> f64 {
-        let m: f64 = self.register.len() as f64;
-        // Added ranges of values if register lenght happens to not be a power of 2
-        if m < 32.0 {
-            0.673
-        } else if m < 64.0 {
-            0.697
-        } else if m < 128.0 {
-            0.709
-        } else {
-            0.7213 / (1.0 + 1.079 / m)
+        Format::Csv => {
+            println!("metric,value");
+            for (name, value) in metrics {
+                println!("{},{}", name, value);
+            }
         }
     }
-
-    /// Count the cardinality of the current set
-    fn count(&self) -> f64 {
-        let alpha: f64 = self.alpha();
-        let m_pow_2: f64 = self.register.len().pow(2) as f64;
-        let indicator: f64 = helpers::indicator(&self.register);
-        alpha * m_pow_2 * indicator
-    }
 }
 
-mod helpers {
-    use std::hash::Hash;
-
-    use hash32::Hasher;
-
-    /// Return a 32 bit hash of a `value`
-    pub fn hash_value_32<T: Hash>(value: &T) -> u32 {
-        let mut hasher = hash32::Murmur3Hasher::default();
-        value.hash(&mut hasher);
-        let hash: u32 = hasher.finish32();
-        hash
-    }
-
-    /// Return `n` big endian (most significant) bits of a `value`
-    pub fn n_be_bits(value: &u32, n: &u32) -> u32 {
-        let shift_amount = 32 - n;
-        value >> shift_amount
-    }
-
-    /// Return `n` least endian bits of a `value`
-    pub fn n_le_bits(value: &u32, n: &u32) -> u32 {
-        let bitmask: u32 = (1 << n) - 1;
-        value & bitmask
-    }
-
-    /// Calculate number of registers based on `index_bits`
-    pub fn registers_from_bits(index_bits: &u8) -> usize {
-        2_usize.checked_pow(*index_bits as u32).unwrap()
-    }
+/// Command line names of the library's `EstimationMethod`s
+#[derive(Clone, Copy, ValueEnum)]
+enum Method {
+    /// Raw estimate with the small and large range corrections
+    Corrected,
+    /// Raw estimate without corrections
+    Raw,
+    /// LogLog-Beta estimate, only available with 14 index bits
+    Beta,
+    /// Geometric mean LogLog estimate
+    Loglog,
+}
 
-    /// Calculate indicator Z
-    pub fn indicator(register: &[u8]) -> f64 {
-        let val: f64 = register
-            .iter()
-            .map(|x| 1_f64 / 2_f64.powi(*x as i32))
-            .sum();
-        1_f64 / val
+impl From<Method> for EstimationMethod {
+    fn from(method: Method) -> Self {
+        match method {
+            Method::Corrected => EstimationMethod::Corrected,
+            Method::Raw => EstimationMethod::Raw,
+            Method::Beta => EstimationMethod::Beta,
+            Method::Loglog => EstimationMethod::LogLog,
+        }
     }
 }
 
-#[derive(Parser)]
-struct Cli {
-    /// Number of index bits to use in HLL
-    #[arg(default_value_t = 6)]
-    index_bits: u8,
-    /// Exponent of 10 for numbers to randomize
-    #[arg(default_value_t = 8)]
-    numbers_exp: u32,
-    /// Exponent of 10 for maximum randomized number
-    #[arg(default_value_t = 12)]
-    max_value_exp: u32
+/// Random number generator seeded with `--seed`, or from entropy without it
+fn generator(args: &Cli) -> StdRng {
+    match args.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    }
 }
 
-fn main() {
-    let args = Cli::parse();
-
+/// Add random numbers from `generator` to `hll` and return the exact number of distinct values
+fn fill_random(hll: &mut HyperLogLog, args: &Cli, generator: &mut StdRng) -> usize {
     let numbers: usize = 10_usize.pow(args.numbers_exp);
     let min: usize = 0;
     let max: usize = 10_usize.pow(args.max_value_exp);
 
-    let mut generator = rand::thread_rng();
-    let mut hll = HLL!(8);
     let mut test_set: HashSet<usize> = HashSet::new();
 
     let bar_style = indicatif::ProgressStyle::with_template(
@@ -155,49 +121,134 @@ fn main() {
         hll.add(&val);
         test_set.insert(val);
     }
-    let estimation = hll.count();
-    let correct = test_set.len();
-    let correct_f64 = correct as f64;
-    let error: f64 = (estimation - correct_f64).abs() / correct_f64;
-    println!("Cardinatity estimated with HashSet lenght\n> {:}", correct);
-    println!("Cardinatity estimated with HLL\n> {:.2}", estimation);
-    println!("Error\n> {:.2}%", error * 100.0);
+    test_set.len()
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Add lines read from stdin to `hll` and return the exact number of distinct lines
+fn fill_stdin(hll: &mut HyperLogLog) -> io::Result<usize> {
+    let mut test_set: HashSet<String> = HashSet::new();
+
+    // Input size is unknown, so show throughput instead of a bar
+    let spinner = ProgressBar::new_spinner().with_style(
+        indicatif::ProgressStyle::with_template(
+            "{spinner} {pos} lines {per_sec} Elapsed: {elapsed_precise}"
+        ).unwrap()
+    );
+
+    for line in io::stdin().lock().lines() {
+        let line = line?;
+        hll.add_str(&line);
+        test_set.insert(line);
+        spinner.inc(1);
+    }
+    spinner.finish();
+    Ok(test_set.len())
+}
 
-    #[test]
-    fn test_registers_from_bits() {
-        assert_eq!(helpers::registers_from_bits(&3), 8);
-    }
+/// Run `trials` independent benchmarks and print the distribution of the error
+fn compare(args: &Cli, trials: u32) {
+    // One generator for all trials, so they see different numbers even when seeded
+    let mut generator = generator(args);
+    let mut errors: Vec<f64> = (0..trials)
+        .map(|_| {
+            let mut hll = HyperLogLog::new(args.index_bits).unwrap();
+            let correct = fill_random(&mut hll, args, &mut generator) as f64;
+            // The method was checked against the precision in `main`
+            (hll.count_with_method(args.method.into()).unwrap() - correct).abs() / correct
+        })
+        .collect();
+    errors.sort_by(f64::total_cmp);
+    let hll = HyperLogLog::new(args.index_bits).unwrap();
+    let decimals = args.decimals;
+    let (min, median, max) =
+        (errors[0] * 100.0, errors[errors.len() / 2] * 100.0, errors[errors.len() - 1] * 100.0);
+    if args.format != Format::Human {
+        let metrics = [
+            ("index_bits", args.index_bits.to_string()),
+            ("registers", hll.num_registers().to_string()),
+            ("theoretical_error_percent", format!("{:.*}", decimals, hll.relative_error() * 100.0)),
+            ("trials", trials.to_string()),
+            ("min_error_percent", format!("{:.*}", decimals, min)),
+            ("median_error_percent", format!("{:.*}", decimals, median)),
+            ("max_error_percent", format!("{:.*}", decimals, max)),
+        ];
+        print_metrics(&metrics, args.format);
+        return;
+    }
+    println!("Precision\n> {} index bits ({} registers)", args.index_bits, hll.num_registers());
+    println!("Theoretical error\n> {:.*}%", decimals, hll.relative_error() * 100.0);
+    println!(
+        "Error over {} trials\n> min {:.*}% median {:.*}% max {:.*}%",
+        trials, decimals, min, decimals, median, decimals, max
+    );
+}
 
-    #[test]
-    fn test_n_be_bits() {
-        let number: u32 = 0b1010_0100_0000_0000_0000_0000_0000_0000;
-        let ret = helpers::n_be_bits(&number, &6);
-        assert_eq!(ret, 0b101001);
-    }
+fn main() -> ExitCode {
+    let args = Cli::parse();
+
+    let mut hll = match HyperLogLog::new(args.index_bits) {
+        Ok(hll) => hll,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            return ExitCode::FAILURE;
+        }
+    };
 
-    #[test]
-    fn test_n_le_bits() {
-        let number: u32 = 0b1010_0100;
-        let ret = helpers::n_le_bits(&number, &3);
-        assert_eq!(ret, 0b100);
+    if let Err(err) = hll.count_with_method(args.method.into()) {
+        eprintln!("error: {}", err);
+        return ExitCode::FAILURE;
     }
 
-    #[test]
-    fn test_hll_add() {
-        let mut hll = HyperLogLog::new(4).unwrap();
-        // Hash should equal 2766284370 = 10100100111000100010011001010010
-        hll.add(&"moros".to_string());
-        assert_eq!(hll.register, vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0])
+    if let Some(trials) = args.compare {
+        compare(&args, trials);
+        return ExitCode::SUCCESS;
     }
 
-    #[test]
-    fn test_hll_macro() {
-        let hll: HyperLogLog = HLL!(6);
-        assert_eq!(hll.index_bits, 6);
+    let correct = if args.stdin {
+        match fill_stdin(&mut hll) {
+            Ok(correct) => correct,
+            Err(err) => {
+                eprintln!("error: {}", err);
+                return ExitCode::FAILURE;
+            }
+        }
+    } else {
+        fill_random(&mut hll, &args, &mut generator(&args))
+    };
+    let estimation = hll.count_with_method(args.method.into()).unwrap();
+    let correct_f64 = correct as f64;
+    // Nothing was added if there were no distinct values
+    let error: f64 = if correct == 0 {
+        0.0
+    } else {
+        (estimation - correct_f64).abs() / correct_f64
+    };
+    let decimals = args.decimals;
+    let registers: Vec<String> = hll.registers_as_slice().iter().map(|x| x.to_string()).collect();
+    if args.format != Format::Human {
+        let mut metrics = vec![
+            ("index_bits", args.index_bits.to_string()),
+            ("registers", hll.num_registers().to_string()),
+            ("exact", correct.to_string()),
+            ("estimate", format!("{:.*}", decimals, estimation)),
+            ("error_percent", format!("{:.*}", decimals, error * 100.0)),
+        ];
+        if args.output_registers {
+            let values = match args.format {
+                Format::Json => format!("[{}]", registers.join(", ")),
+                _ => registers.join(" "),
+            };
+            metrics.push(("register_values", values));
+        }
+        print_metrics(&metrics, args.format);
+        return ExitCode::SUCCESS;
+    }
+    println!("Precision\n> {} index bits ({} registers)", args.index_bits, hll.num_registers());
+    println!("Cardinatity estimated with HashSet lenght\n> {:}", correct);
+    println!("Cardinatity estimated with HLL\n> {:.*}", decimals, estimation);
+    println!("Error\n> {:.*}%", decimals, error * 100.0);
+    if args.output_registers {
+        println!("Registers\n> {}", registers.join(" "));
     }
+    ExitCode::SUCCESS
 }
@@ -0,0 +1,85 @@
+//! Pool recycling HyperLogLog register allocations
+
+use crate::{helpers, HyperLogLog};
+
+/// Pool of register buffers for sets of one precision
+///
+/// Sets are handed out with `acquire` and given back with `release`, after which their register
+/// allocation is reused by the next `acquire`. This avoids an allocation per set when many
+/// short-lived sets are created.
+#[derive(Debug)]
+pub struct HllPool {
+    index_bits: u8,
+    free: Vec<Vec<u8>>,
+}
+
+impl HllPool {
+    /// Create an empty pool for sets with `index_bits`
+    pub fn new(index_bits: u8) -> Result<Self, String> {
+        helpers::validate_index_bits(index_bits)?;
+        Ok(Self { index_bits, free: Vec::new() })
+    }
+
+    /// Get an empty set, reusing a released register allocation if there is one
+    pub fn acquire(&mut self) -> HyperLogLog {
+        let m: usize = helpers::registers_from_bits(&self.index_bits);
+        match self.free.pop() {
+            Some(mut register) => {
+                register.clear();
+                register.resize(m, 0);
+                HyperLogLog::from_zeroed_buffer(self.index_bits, register)
+            }
+            None => HyperLogLog::new(self.index_bits).unwrap(),
+        }
+    }
+
+    /// Give the register allocation of `hll` back to the pool
+    pub fn release(&mut self, hll: HyperLogLog) -> Result<(), String> {
+        if hll.index_bits != self.index_bits {
+            return Err(
+                format!(
+                    "Pool holds sets with {} index bits (was {})", self.index_bits, hll.index_bits
+                )
+            );
+        }
+        self.free.push(hll.register);
+        Ok(())
+    }
+
+    /// Number of register allocations waiting to be reused
+    pub fn pooled(&self) -> usize {
+        self.free.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pool_reuses_allocation() {
+        let mut pool = HllPool::new(12).unwrap();
+        let mut hll = pool.acquire();
+        for i in 0..1000_u32 {
+            hll.add(&i);
+        }
+        let pointer = hll.registers_as_slice().as_ptr();
+        pool.release(hll).unwrap();
+        assert_eq!(pool.pooled(), 1);
+
+        let hll = pool.acquire();
+        assert_eq!(pool.pooled(), 0);
+        assert_eq!(hll.registers_as_slice().as_ptr(), pointer);
+        assert_eq!(hll.num_registers(), 4096);
+        assert!(hll.is_empty());
+        assert_eq!(hll.count(), 0.0);
+    }
+
+    #[test]
+    fn test_pool_rejects_other_precision() {
+        let mut pool = HllPool::new(12).unwrap();
+        assert!(pool.release(HyperLogLog::new(10).unwrap()).is_err());
+        assert_eq!(pool.pooled(), 0);
+        assert!(HllPool::new(3).is_err());
+    }
+}
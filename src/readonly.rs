@@ -0,0 +1,80 @@
+//! Immutable HyperLogLog shareable between threads
+
+use std::ops::Deref;
+use std::sync::Arc;
+
+use crate::HyperLogLog;
+
+/// Immutable HLL set whose clones share the same registers
+///
+/// All read-only methods of `HyperLogLog` are available through `Deref`.
+#[derive(Debug, Clone)]
+pub struct ReadOnlyHll {
+    inner: Arc<HyperLogLog>,
+}
+
+impl ReadOnlyHll {
+    /// Merge with `other` into a new mutable set
+    pub fn merged(&self, other: &HyperLogLog) -> Result<HyperLogLog, String> {
+        let mut hll = HyperLogLog::clone(&self.inner);
+        hll.merge(other)?;
+        Ok(hll)
+    }
+}
+
+impl Deref for ReadOnlyHll {
+    type Target = HyperLogLog;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl HyperLogLog {
+    /// Freeze the set into a cheaply clonable read-only view
+    pub fn into_readonly(self) -> ReadOnlyHll {
+        ReadOnlyHll { inner: Arc::new(self) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn test_readonly_threads() {
+        let mut hll = HyperLogLog::new(12).unwrap();
+        for i in 0..10_000_u32 {
+            hll.add(&i);
+        }
+        let expected = hll.count();
+        let readonly = hll.into_readonly();
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let view = readonly.clone();
+                thread::spawn(move || view.count())
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), expected);
+        }
+        assert_eq!(
+            readonly.clone().registers_as_slice().as_ptr(),
+            readonly.registers_as_slice().as_ptr()
+        );
+    }
+
+    #[test]
+    fn test_readonly_merged() {
+        let mut a = HyperLogLog::new(8).unwrap();
+        let mut b = HyperLogLog::new(8).unwrap();
+        a.add(&1_u32);
+        b.add(&2_u32);
+        let mut expected = a.clone();
+        expected.merge(&b).unwrap();
+        let merged = a.into_readonly().merged(&b).unwrap();
+        assert_eq!(merged.registers_as_slice(), expected.registers_as_slice());
+    }
+}
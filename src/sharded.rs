@@ -0,0 +1,89 @@
+//! HyperLogLog split into shards for adding from many threads
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::{helpers, HyperLogLog};
+
+/// HLL set made of sub-sets that threads add to without contending for one lock
+///
+/// Each thread adds to the shard picked by hashing its thread id, so threads mostly lock
+/// different shards. All shards hash elements like `HyperLogLog::new`, so `collapse` merging
+/// them gives the same registers as adding every element to a single set.
+#[derive(Debug)]
+pub struct ShardedHll {
+    shards: Vec<Mutex<HyperLogLog>>,
+    index_bits: u8,
+}
+
+impl ShardedHll {
+    /// Create a new sharded set of `shards` sub-sets with `index_bits` each
+    pub fn new(index_bits: u8, shards: usize) -> Result<Self, String> {
+        helpers::validate_index_bits(index_bits)?;
+        if shards == 0 {
+            return Err("Number of shards must be positive".to_string());
+        }
+        let shards = (0..shards).map(|_| Mutex::new(HyperLogLog::new(index_bits).unwrap()));
+        Ok(Self { shards: shards.collect(), index_bits })
+    }
+
+    /// Number of shards
+    pub fn num_shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Add a new hashable element to the shard of the current thread
+    pub fn add<T: Hash>(&self, value: &T) {
+        let mut hasher = DefaultHasher::new();
+        thread::current().id().hash(&mut hasher);
+        let shard = &self.shards[hasher.finish() as usize % self.shards.len()];
+        shard.lock().unwrap().add(value);
+    }
+
+    /// Merge all shards into a single set
+    pub fn collapse(&self) -> HyperLogLog {
+        let mut hll = HyperLogLog::new(self.index_bits).unwrap();
+        for shard in &self.shards {
+            // All shards share the precision and hashing of `hll`
+            hll.merge(&shard.lock().unwrap()).unwrap();
+        }
+        hll
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sharded_invalid() {
+        assert!(ShardedHll::new(3, 4).is_err());
+        assert!(ShardedHll::new(8, 0).is_err());
+    }
+
+    #[test]
+    fn test_sharded_threads() {
+        let sharded = ShardedHll::new(10, 4).unwrap();
+        assert_eq!(sharded.num_shards(), 4);
+        thread::scope(|scope| {
+            for t in 0..8_u32 {
+                let sharded = &sharded;
+                // Overlapping ranges, so the shards share elements
+                scope.spawn(move || {
+                    for i in t * 1000..t * 1000 + 2000 {
+                        sharded.add(&i);
+                    }
+                });
+            }
+        });
+        let mut expected = HyperLogLog::new(10).unwrap();
+        for i in 0..9000_u32 {
+            expected.add(&i);
+        }
+        let collapsed = sharded.collapse();
+        assert_eq!(collapsed.registers_as_slice(), expected.registers_as_slice());
+        assert_eq!(collapsed.count(), expected.count());
+    }
+}
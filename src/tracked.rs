@@ -0,0 +1,83 @@
+//! HyperLogLog recording its estimates over time
+
+use std::ops::{Deref, DerefMut};
+
+use crate::{HyperLogLog, QuantileDigest};
+
+/// Centroids kept by the summary behind `TrackedHll::record_and_quantile`
+const ESTIMATE_CENTROIDS: usize = 200;
+
+/// HLL set with a summary of the estimates recorded while it is filled
+///
+/// Meant for monitoring how the estimate moves, the set itself does not pay for the summary.
+/// All methods of `HyperLogLog` are available through `Deref` and `DerefMut`.
+#[derive(Debug, Clone)]
+pub struct TrackedHll {
+    hll: HyperLogLog,
+    estimates: QuantileDigest,
+}
+
+impl TrackedHll {
+    /// Start recording the estimates of `hll`
+    pub fn new(hll: HyperLogLog) -> Self {
+        Self { hll, estimates: QuantileDigest::new(ESTIMATE_CENTROIDS) }
+    }
+
+    /// Record the current `count()` and return the `p`-th quantile of all recorded estimates
+    ///
+    /// Estimates go into a bounded `QuantileDigest`, so quantiles are exact for the first 200
+    /// records and approximate afterwards. `p` is clamped to 0.0..=1.0.
+    pub fn record_and_quantile(&mut self, p: f64) -> f64 {
+        self.estimates.insert(self.hll.count());
+        self.estimates.quantile(p)
+    }
+
+    /// Approximate number of bytes used by the set and the summary
+    pub fn memory_bytes(&self) -> usize {
+        self.hll.memory_bytes() + std::mem::size_of::<QuantileDigest>()
+            + self.estimates.heap_bytes()
+    }
+
+    /// Stop recording and return the set
+    pub fn into_inner(self) -> HyperLogLog {
+        self.hll
+    }
+}
+
+impl Deref for TrackedHll {
+    type Target = HyperLogLog;
+
+    fn deref(&self) -> &Self::Target {
+        &self.hll
+    }
+}
+
+impl DerefMut for TrackedHll {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.hll
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_quantile() {
+        let mut hll = TrackedHll::new(HyperLogLog::new(10).unwrap());
+        let mut estimates: Vec<f64> = Vec::new();
+        let mut median = 0.0;
+        for i in 0..51_u32 {
+            for j in 0..i * 10 {
+                hll.add(&(i * 1_000 + j));
+            }
+            estimates.push(hll.count());
+            median = hll.record_and_quantile(0.5);
+        }
+        estimates.sort_by(f64::total_cmp);
+        assert_eq!(median, estimates[25]);
+        assert_eq!(hll.record_and_quantile(1.0), estimates[50]);
+        assert!(hll.memory_bytes() > hll.hll.memory_bytes());
+        assert_eq!(hll.into_inner().count(), estimates[50]);
+    }
+}
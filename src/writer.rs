@@ -0,0 +1,97 @@
+//! `io::Write` adapter adding lines of a byte stream to a HyperLogLog
+
+use std::io;
+
+use crate::HyperLogLog;
+
+/// Writer adding every newline terminated line written to it to a HLL
+///
+/// Lines are added with `HyperLogLog::add_bytes` without the newline. Lines may be split across
+/// any number of `write` calls, a final line without a trailing newline is added when the writer
+/// is dropped.
+#[derive(Debug)]
+pub struct HllWriter<'a> {
+    hll: &'a mut HyperLogLog,
+    line: Vec<u8>,
+}
+
+impl<'a> HllWriter<'a> {
+    /// Create a new writer adding lines to `hll`
+    pub fn new(hll: &'a mut HyperLogLog) -> Self {
+        Self { hll, line: Vec::new() }
+    }
+}
+
+impl io::Write for HllWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut rest = buf;
+        while let Some(position) = rest.iter().position(|x| *x == b'\n') {
+            if self.line.is_empty() {
+                self.hll.add_bytes(&rest[..position]);
+            } else {
+                self.line.extend_from_slice(&rest[..position]);
+                self.hll.add_bytes(&self.line);
+                self.line.clear();
+            }
+            rest = &rest[position + 1..];
+        }
+        self.line.extend_from_slice(rest);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for HllWriter<'_> {
+    fn drop(&mut self) {
+        if !self.line.is_empty() {
+            self.hll.add_bytes(&self.line);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    #[test]
+    fn test_writer_chunks() {
+        let mut input: Vec<u8> = Vec::new();
+        let mut expected = HyperLogLog::new(10).unwrap();
+        for i in 0..3000 {
+            let line = format!("line-{}", i % 1000);
+            expected.add_bytes(line.as_bytes());
+            input.extend_from_slice(line.as_bytes());
+            input.push(b'\n');
+        }
+        input.extend_from_slice(b"last");
+        expected.add_bytes(b"last");
+
+        let mut hll = HyperLogLog::new(10).unwrap();
+        {
+            let mut writer = HllWriter::new(&mut hll);
+            for chunk in input.chunks(7) {
+                writer.write_all(chunk).unwrap();
+            }
+        }
+        assert_eq!(hll.registers_as_slice(), expected.registers_as_slice());
+        let error = (hll.count() - 1001.0).abs() / 1001.0;
+        assert!(error < 0.05, "{}", error);
+    }
+
+    #[test]
+    fn test_writer_copy() {
+        let mut hll = HyperLogLog::new(8).unwrap();
+        let mut reader: &[u8] = b"a\nb\na\n\nc\n";
+        io::copy(&mut reader, &mut HllWriter::new(&mut hll)).unwrap();
+        let mut expected = HyperLogLog::new(8).unwrap();
+        for line in ["a", "b", "", "c"] {
+            expected.add_str(line);
+        }
+        assert_eq!(hll.registers_as_slice(), expected.registers_as_slice());
+    }
+}
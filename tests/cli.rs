@@ -0,0 +1,190 @@
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_hll_rs"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_invalid_index_bits() {
+    for index_bits in ["2", "20"] {
+        let output = run(&[index_bits, "2", "3"]);
+        assert!(!output.status.success());
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains("Number of index bits must be between 4 and 16"), "{}", stderr);
+        assert!(!stderr.contains("panicked"), "{}", stderr);
+    }
+}
+
+#[test]
+fn test_valid_index_bits() {
+    let output = run(&["6", "2", "3"]);
+    assert!(output.status.success());
+}
+
+#[test]
+fn test_reports_precision() {
+    for (index_bits, registers) in [("4", 16), ("10", 1024)] {
+        let output = run(&[index_bits, "2", "3"]);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let expected = format!("> {} index bits ({} registers)", index_bits, registers);
+        assert!(stdout.contains(&expected), "{}", stdout);
+    }
+}
+
+#[test]
+fn test_output_registers() {
+    let output = run(&["5", "2", "3", "--output-registers"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let registers = stdout
+        .lines()
+        .skip_while(|line| *line != "Registers")
+        .nth(1)
+        .unwrap();
+    let values: Vec<u8> = registers
+        .trim_start_matches("> ")
+        .split(' ')
+        .map(|x| x.parse().unwrap())
+        .collect();
+    assert_eq!(values.len(), 32);
+}
+
+#[test]
+fn test_compare() {
+    let output = run(&["10", "3", "6", "--compare", "15", "--seed", "7"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let distribution = stdout
+        .lines()
+        .skip_while(|line| *line != "Error over 15 trials")
+        .nth(1)
+        .unwrap();
+    let values: Vec<f64> = distribution
+        .split(' ')
+        .filter_map(|x| x.strip_suffix('%'))
+        .map(|x| x.parse().unwrap())
+        .collect();
+    assert_eq!(values.len(), 3);
+    assert!(values[0] <= values[1] && values[1] <= values[2]);
+    // Theoretical error with 1024 registers is 1.04 / 32
+    assert!(values[1] < 104.0 / 32.0, "{}", distribution);
+}
+
+#[test]
+fn test_seed() {
+    let seeded = run(&["8", "3", "6", "--seed", "7"]);
+    assert!(seeded.status.success());
+    assert_eq!(run(&["8", "3", "6", "--seed", "7"]).stdout, seeded.stdout);
+    assert_ne!(run(&["8", "3", "6", "--seed", "8"]).stdout, seeded.stdout);
+}
+
+fn run_stdin(args: &[&str], input: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_hll_rs"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn test_stdin_empty() {
+    let output = run_stdin(&["--stdin"], b"");
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Cardinatity estimated with HashSet lenght\n> 0"), "{}", stdout);
+}
+
+#[test]
+fn test_stdin_conflicts_with_compare() {
+    let output = run_stdin(&["--stdin", "--compare", "3"], b"a\n");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("cannot be used with"), "{}", stderr);
+}
+
+#[test]
+fn test_stdin_lines() {
+    let output = run_stdin(&["8", "--stdin"], b"a\nb\na\nc\n");
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Cardinatity estimated with HashSet lenght\n> 3"), "{}", stdout);
+}
+
+#[test]
+fn test_methods() {
+    // LogLog-Beta only supports 14 index bits
+    let methods = [("8", "corrected"), ("8", "raw"), ("14", "beta"), ("8", "loglog")];
+    for (index_bits, method) in methods {
+        let output = run(&[index_bits, "3", "6", "--method", method]);
+        assert!(output.status.success(), "{}", method);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let estimate = stdout
+            .lines()
+            .skip_while(|line| *line != "Cardinatity estimated with HLL")
+            .nth(1)
+            .unwrap();
+        let value: f64 = estimate.trim_start_matches("> ").parse().unwrap();
+        assert!(value > 0.0, "{}: {}", method, value);
+    }
+    assert!(!run(&["8", "3", "6", "--method", "plus"]).status.success());
+    let output = run(&["8", "3", "6", "--method", "beta"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("only available with 14 index bits (was 8)"), "{}", stderr);
+}
+
+#[test]
+fn test_decimals() {
+    let output = run(&["8", "2", "3", "--decimals", "4"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let estimate = stdout
+        .lines()
+        .skip_while(|line| *line != "Cardinatity estimated with HLL")
+        .nth(1)
+        .unwrap();
+    let (_, fraction) = estimate.split_once('.').unwrap();
+    assert_eq!(fraction.len(), 4, "{}", estimate);
+}
+
+#[test]
+fn test_format_csv() {
+    let output = run(&["8", "2", "3", "--format", "csv", "--output-registers"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let mut lines = stdout.lines();
+    assert_eq!(lines.next(), Some("metric,value"));
+    let rows: Vec<(&str, &str)> = lines.map(|x| x.split_once(',').unwrap()).collect();
+    let names: Vec<&str> = rows.iter().map(|(name, _)| *name).collect();
+    assert_eq!(
+        names,
+        ["index_bits", "registers", "exact", "estimate", "error_percent", "register_values"]
+    );
+    for (name, value) in &rows[..5] {
+        assert!(value.parse::<f64>().is_ok(), "{}: {}", name, value);
+    }
+    assert_eq!(rows[5].1.split(' ').count(), 256);
+}
+
+#[test]
+fn test_format_json() {
+    let single: &[&str] = &["8", "2", "3", "--format", "json"];
+    let compare: &[&str] = &["8", "2", "3", "--format", "json", "--compare", "3"];
+    for args in [single, compare] {
+        let output = run(args);
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let inner = stdout.trim().strip_prefix('{').unwrap().strip_suffix('}').unwrap();
+        for entry in inner.split(", ") {
+            let (name, value) = entry.split_once(": ").unwrap();
+            assert!(name.starts_with('"') && name.ends_with('"'), "{}", name);
+            assert!(value.parse::<f64>().is_ok(), "{}: {}", name, value);
+        }
+    }
+}